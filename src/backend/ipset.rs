@@ -0,0 +1,243 @@
+use super::SetBackend;
+use crate::source::{SetElement, SetTemplate, IP};
+use anyhow::{anyhow, Result};
+use nftables::schema;
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+/// Default `maxelem` of ipset
+const MIN_MAX_ELEMENTS: usize = 65536;
+
+/// `hash:net` set managed via `ipset restore`
+pub(crate) struct IpSet {
+    name: String,
+    family: &'static str,
+    timeout: Option<u32>,
+}
+
+impl IpSet {
+    pub(crate) fn with_template(name: &str, template: &SetTemplate) -> Self {
+        let is_ipv6 = template.set_type == schema::SetTypeValue::Single(schema::SetType::Ipv6Addr);
+
+        Self {
+            name: name.to_string(),
+            family: if is_ipv6 { "inet6" } else { "inet" },
+            // Elements get own timeouts only in sets created with default one
+            timeout: template
+                .timeout
+                .or_else(|| template.has_timeouts().then_some(0)),
+        }
+    }
+
+    /// Set is sized for given number of elements, or gets default size
+    pub(crate) fn create_command(&self, name: &str, max_elements: Option<usize>) -> String {
+        let mut command = format!("create {name} hash:net family {}", self.family);
+
+        if let Some(max_elements) = max_elements {
+            let _ = write!(command, " maxelem {}", max_elements.max(MIN_MAX_ELEMENTS));
+        }
+
+        if let Some(timeout) = self.timeout {
+            let _ = write!(command, " timeout {timeout}");
+        }
+
+        command.push_str(" -exist\n");
+        command
+    }
+
+    /// Existing sets are never created again, as `-exist` accepts
+    /// only identical parameters and `maxelem` changes with lists
+    fn exists(name: &str) -> bool {
+        Command::new("ipset")
+            .args(["list", "-name", name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    fn restore(&self, script: &str) -> Result<()> {
+        let mut child = Command::new("ipset")
+            .arg("restore")
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes())?;
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "ipset restore failed for {} set: {}",
+                self.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Set names are limited to 31 characters
+fn temporary_name(name: &str) -> String {
+    name.chars().take(27).chain("_tmp".chars()).collect()
+}
+
+fn render_elements(script: &mut String, set_name: &str, entries: Vec<SetElement>) {
+    for element in entries {
+        let _ = write!(script, "add {set_name} {}", element.ip);
+        if let Some(timeout) = element.timeout {
+            let _ = write!(script, " timeout {timeout}");
+        }
+        script.push_str(" -exist\n");
+    }
+}
+
+impl SetBackend for IpSet {
+    fn flush(&self) -> Result<()> {
+        self.restore(&format!("flush {}\n", self.name))
+    }
+
+    fn load_entries(&self, entries: Vec<SetElement>, _chunk_size: usize) -> Result<()> {
+        log::info!(
+            "Downloaded {} elements for {} set. Applying...",
+            entries.len(),
+            self.name
+        );
+
+        let mut script = String::new();
+        if !Self::exists(&self.name) {
+            script = self.create_command(&self.name, Some(entries.len()));
+        }
+        render_elements(&mut script, &self.name, entries);
+
+        self.restore(&script)
+    }
+
+    /// Fills temporary set and swaps it with the target one,
+    /// so firewall never sees the set partially filled
+    fn replace_entries(&self, entries: Vec<SetElement>, _chunk_size: usize) -> Result<()> {
+        log::info!(
+            "Downloaded {} elements for {} set. Applying...",
+            entries.len(),
+            self.name
+        );
+
+        let temporary = temporary_name(&self.name);
+
+        // Size of the live set doesn't matter, as the temporary one takes its place
+        let mut script = String::new();
+        if !Self::exists(&self.name) {
+            script = self.create_command(&self.name, None);
+        }
+
+        // Set left by failed update may have another size
+        if Self::exists(&temporary) {
+            let _ = writeln!(script, "destroy {temporary}");
+        }
+
+        script.push_str(&self.create_command(&temporary, Some(entries.len())));
+        render_elements(&mut script, &temporary, entries);
+        let _ = writeln!(script, "swap {temporary} {}", self.name);
+        let _ = writeln!(script, "destroy {temporary}");
+
+        self.restore(&script)
+    }
+
+    fn list_entries(&self) -> Result<Vec<IP>> {
+        let output = Command::new("ipset").args(["save", &self.name]).output()?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Cannot list {} set: {}",
+                self.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let entries = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("add "))
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .filter_map(|entry| entry.parse().ok())
+            .collect();
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_elements, temporary_name, IpSet};
+    use crate::source::{SetElement, SetTemplate, IP};
+    use nftables::schema;
+
+    #[test]
+    fn create_command() {
+        let template = SetTemplate {
+            set_type: schema::SetTypeValue::Single(schema::SetType::Ipv6Addr),
+            timeout: Some(600),
+            ..SetTemplate::default()
+        };
+        let set = IpSet::with_template("blocked", &template);
+
+        assert_eq!(
+            set.create_command("blocked", None),
+            "create blocked hash:net family inet6 timeout 600 -exist\n"
+        );
+        assert_eq!(
+            set.create_command("blocked", Some(100)),
+            "create blocked hash:net family inet6 maxelem 65536 timeout 600 -exist\n"
+        );
+        assert_eq!(
+            set.create_command("blocked", Some(100_000)),
+            "create blocked hash:net family inet6 maxelem 100000 timeout 600 -exist\n"
+        );
+    }
+
+    #[test]
+    fn create_with_timeout_flag() {
+        let template = SetTemplate {
+            flags: Some([schema::SetFlag::Timeout].into()),
+            ..SetTemplate::default()
+        };
+        let set = IpSet::with_template("resolved", &template);
+
+        assert_eq!(
+            set.create_command("resolved", None),
+            "create resolved hash:net family inet timeout 0 -exist\n"
+        );
+    }
+
+    #[test]
+    fn render_elements_with_timeouts() {
+        let mut script = String::new();
+        let plain = SetElement::from("1.1.1.1".parse::<IP>().unwrap());
+        let with_timeout = SetElement {
+            ip: "10.0.0.0/8".parse().unwrap(),
+            timeout: Some(60),
+            comment: Some("ignored".to_string()),
+        };
+        render_elements(&mut script, "blocked", vec![plain, with_timeout]);
+
+        assert_eq!(
+            script,
+            "add blocked 1.1.1.1 -exist\nadd blocked 10.0.0.0/8 timeout 60 -exist\n"
+        );
+    }
+
+    #[test]
+    fn truncate_temporary_name() {
+        assert_eq!(temporary_name("blocked"), "blocked_tmp");
+
+        let name = "a".repeat(40);
+        let temporary = temporary_name(&name);
+        assert_eq!(temporary.len(), 31);
+        assert!(temporary.ends_with("_tmp"));
+    }
+}
@@ -0,0 +1,93 @@
+mod ipset;
+#[cfg(feature = "netlink")]
+mod netlink;
+mod netns;
+
+#[cfg(feature = "netlink")]
+use self::netlink::NetlinkSet;
+pub(crate) use self::{
+    ipset::IpSet,
+    netns::{netns_path, run_in_netns, NetnsSet},
+};
+use crate::{
+    nf_helpers::{NfSet, NftOptions, RoutingOptions},
+    source::{SetElement, SetTemplate, IP},
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Kernel facility which holds the sets
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Backend {
+    #[default]
+    Nftables,
+    /// Legacy firmware with iptables. Table name is not used
+    Ipset,
+    /// `nf_tables` over netlink without `nft` binary
+    #[cfg(feature = "netlink")]
+    Netlink,
+}
+
+impl Backend {
+    pub(crate) fn open_set(
+        self,
+        name: &str,
+        table_name: &str,
+        template: &SetTemplate,
+        nft: &NftOptions,
+        routing: Option<&RoutingOptions>,
+    ) -> Box<dyn SetBackend> {
+        match self {
+            Self::Nftables => Box::new(NfSet::with_template(
+                name,
+                table_name,
+                template.clone(),
+                nft.clone(),
+                routing.cloned(),
+            )),
+            Self::Ipset => Box::new(IpSet::with_template(name, template)),
+            #[cfg(feature = "netlink")]
+            Self::Netlink => Box::new(NetlinkSet::with_template(name, table_name, template)),
+        }
+    }
+}
+
+/// Element with packets and bytes it matched
+pub(crate) struct ElementCounter {
+    pub(crate) ip: IP,
+    pub(crate) packets: u64,
+    pub(crate) bytes: u64,
+    pub(crate) comment: Option<String>,
+}
+
+pub(crate) trait SetBackend: Send + Sync {
+    fn flush(&self) -> Result<()>;
+
+    fn load_entries(&self, entries: Vec<SetElement>, chunk_size: usize) -> Result<()>;
+
+    /// Replaces set contents. Backends may do this atomically
+    fn replace_entries(&self, entries: Vec<SetElement>, chunk_size: usize) -> Result<()> {
+        self.flush()?;
+        self.load_entries(entries, chunk_size)
+    }
+
+    /// Reads elements which are currently in kernel
+    fn list_entries(&self) -> Result<Vec<IP>>;
+
+    /// Reads elements with traffic they matched. Set must have counters
+    fn list_counters(&self) -> Result<Vec<ElementCounter>> {
+        Err(anyhow!("Counters are not supported by this backend"))
+    }
+
+    /// Creates chain with rules matching the set.
+    /// Other backends ignore routing, which is reported at config load
+    fn apply_routing(&self, _routing: &RoutingOptions) -> Result<()> {
+        Ok(())
+    }
+
+    /// Deletes chain created by `apply_routing`
+    fn remove_routing(&self, _routing: &RoutingOptions) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,945 @@
+//! Minimal `nf_tables` netlink client which manages set elements
+//! the same way `nft` does but without spawning it
+
+use super::{ElementCounter, SetBackend};
+use crate::{
+    nf_helpers::keyword,
+    source::{SetElement, SetTemplate, IP},
+};
+use anyhow::{anyhow, Result};
+use ipnet::{Ipv4Subnets, Ipv6Subnets};
+use nftables::schema;
+use std::{
+    io,
+    mem::size_of,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+const NETLINK_NETFILTER: i32 = 12;
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+const NFNL_MSG_BATCH_BEGIN: u16 = 16;
+const NFNL_MSG_BATCH_END: u16 = 17;
+
+const NFT_MSG_NEWSETELEM: u16 = 12;
+const NFT_MSG_GETSETELEM: u16 = 13;
+const NFT_MSG_DELSETELEM: u16 = 14;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_DUMP: u16 = 0x300;
+const NLM_F_CREATE: u16 = 0x400;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLA_F_NESTED: u16 = 0x8000;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const NFTA_SET_ELEM_LIST_TABLE: u16 = 1;
+const NFTA_SET_ELEM_LIST_SET: u16 = 2;
+const NFTA_SET_ELEM_LIST_ELEMENTS: u16 = 3;
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_SET_ELEM_KEY: u16 = 1;
+const NFTA_SET_ELEM_FLAGS: u16 = 3;
+const NFTA_SET_ELEM_TIMEOUT: u16 = 4;
+const NFTA_SET_ELEM_USERDATA: u16 = 6;
+const NFTA_SET_ELEM_EXPR: u16 = 7;
+const NFTA_SET_ELEM_EXPRESSIONS: u16 = 10;
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+const NFTA_COUNTER_BYTES: u16 = 1;
+const NFTA_COUNTER_PACKETS: u16 = 2;
+/// Type of comment record inside element userdata
+const NFTNL_UDATA_SET_ELEM_COMMENT: u8 = 0;
+const NFTA_DATA_VALUE: u16 = 1;
+const NFT_SET_ELEM_INTERVAL_END: u32 = 1;
+
+const HEADERS_LEN: usize = 16 + 4;
+/// Attribute length is 16-bit, so elements are split into several messages
+const MAX_ELEMENTS_LEN: usize = 60 * 1024;
+/// Keep batches below default socket buffer size
+const MAX_BATCH_LEN: usize = 160 * 1024;
+
+pub(crate) struct NetlinkSet {
+    family: u8,
+    table: String,
+    name: String,
+    is_interval: bool,
+    is_ipv6: bool,
+}
+
+impl NetlinkSet {
+    pub(crate) fn with_template(name: &str, table_name: &str, template: &SetTemplate) -> Self {
+        // NFPROTO_* values
+        let family = match keyword(&template.family).as_str() {
+            "ip" => 2,
+            "arp" => 3,
+            "netdev" => 5,
+            "bridge" => 7,
+            "ip6" => 10,
+            _ => 1,
+        };
+
+        let is_interval = template
+            .flags
+            .as_ref()
+            .is_some_and(|flags| flags.contains(&schema::SetFlag::Interval));
+
+        Self {
+            family,
+            table: table_name.to_string(),
+            name: name.to_string(),
+            is_interval,
+            is_ipv6: template.set_type == schema::SetTypeValue::Single(schema::SetType::Ipv6Addr),
+        }
+    }
+
+    fn begin_set_message(&self, message: &mut Message, msg_type: u16, flags: u16) -> Result<usize> {
+        let start = message.begin(msg_type, flags, self.family);
+        message.put_str(NFTA_SET_ELEM_LIST_TABLE, &self.table)?;
+        message.put_str(NFTA_SET_ELEM_LIST_SET, &self.name)?;
+        Ok(start)
+    }
+
+    /// Interval sets store ranges as start key and key after the end.
+    /// Kernel rejects overlapping intervals, so they are merged like `nft` does
+    fn kernel_elements(&self, entries: Vec<SetElement>) -> Vec<KernelElement> {
+        let entries = entries.into_iter().filter(|element| {
+            let is_same_family = element.ip.to_network().addr().is_ipv6() == self.is_ipv6;
+            if !is_same_family {
+                log::debug!(
+                    "Skipping {} of another family for {} set",
+                    element.ip,
+                    self.name
+                );
+            }
+            is_same_family
+        });
+
+        if !self.is_interval {
+            return entries
+                .filter_map(|element| match element.ip {
+                    IP::Single(address) => Some(KernelElement {
+                        keys: vec![(address, 0)],
+                        timeout: element.timeout,
+                        comment: element.comment,
+                    }),
+                    IP::Network(network) => {
+                        log::debug!("Skipping {network} as {} set is not interval", self.name);
+                        None
+                    }
+                })
+                .collect();
+        }
+
+        let mut ranges: Vec<_> = entries
+            .map(|element| {
+                let network = element.ip.to_network();
+                Range {
+                    start: network.network(),
+                    end: network.broadcast(),
+                    timeout: element.timeout,
+                    comment: element.comment,
+                }
+            })
+            .collect();
+        ranges.sort_unstable_by_key(|range| range.start);
+
+        merge_ranges(ranges)
+            .into_iter()
+            .map(|range| {
+                let mut keys = vec![(range.start, 0)];
+                keys.extend(next_address(range.end).map(|key| (key, NFT_SET_ELEM_INTERVAL_END)));
+                KernelElement {
+                    keys,
+                    timeout: range.timeout,
+                    comment: range.comment,
+                }
+            })
+            .collect()
+    }
+}
+
+impl SetBackend for NetlinkSet {
+    /// Deleting elements without specifying them flushes the set
+    fn flush(&self) -> Result<()> {
+        let socket = Socket::open()?;
+
+        let mut message = Message::default();
+        let start = self.begin_set_message(&mut message, NFT_MSG_DELSETELEM, NLM_F_ACK)?;
+        message.end(start)?;
+
+        socket.send_batch(&[message])
+    }
+
+    fn load_entries(&self, entries: Vec<SetElement>, chunk_size: usize) -> Result<()> {
+        log::info!(
+            "Downloaded {} elements for {} set. Applying...",
+            entries.len(),
+            self.name
+        );
+
+        let socket = Socket::open()?;
+
+        let mut batch = vec![];
+        let mut batch_len = 0;
+        let mut batch_elements = 0;
+        let mut message: Option<ElementsMessage> = None;
+
+        for element in self.kernel_elements(entries) {
+            let current = match &mut message {
+                Some(current) => current,
+                None => message.insert(ElementsMessage::new(self)?),
+            };
+            for (key, flags) in element.keys {
+                // Interval end only marks where the start element ends
+                if flags == 0 {
+                    current.put_element(key, flags, element.timeout, element.comment.as_deref())?;
+                } else {
+                    current.put_element(key, flags, None, None)?;
+                }
+            }
+            batch_elements += 1;
+
+            if current.message.buffer.len() >= MAX_ELEMENTS_LEN {
+                let finished = message.take().map(ElementsMessage::finish).transpose()?;
+                batch_len += finished.as_ref().map_or(0, |message| message.buffer.len());
+                batch.extend(finished);
+            }
+
+            if batch_len >= MAX_BATCH_LEN || batch_elements >= chunk_size {
+                batch.extend(message.take().map(ElementsMessage::finish).transpose()?);
+                socket.send_batch(&batch)?;
+                batch.clear();
+                batch_len = 0;
+                batch_elements = 0;
+            }
+        }
+
+        batch.extend(message.map(ElementsMessage::finish).transpose()?);
+        if !batch.is_empty() {
+            socket.send_batch(&batch)?;
+        }
+
+        Ok(())
+    }
+
+    fn list_entries(&self) -> Result<Vec<IP>> {
+        let socket = Socket::open()?;
+
+        let mut message = Message::default();
+        let start = self.begin_set_message(&mut message, NFT_MSG_GETSETELEM, NLM_F_DUMP)?;
+        message.end(start)?;
+        socket.send(&message.buffer)?;
+
+        let mut keys = vec![];
+        socket.receive_dump(|payload| parse_elements(payload, &mut keys))?;
+
+        Ok(keys_to_entries(keys, self.is_interval))
+    }
+
+    fn list_counters(&self) -> Result<Vec<ElementCounter>> {
+        let socket = Socket::open()?;
+
+        let mut message = Message::default();
+        let start = self.begin_set_message(&mut message, NFT_MSG_GETSETELEM, NLM_F_DUMP)?;
+        message.end(start)?;
+        socket.send(&message.buffer)?;
+
+        let mut keys = vec![];
+        socket.receive_dump(|payload| {
+            for element in dumped_elements(payload) {
+                if let Some(key) = element.key {
+                    keys.push((key, element.flags, element));
+                }
+            }
+        })?;
+
+        let counters = join_keys(keys, self.is_interval)
+            .into_iter()
+            .map(|(ip, element)| ElementCounter {
+                ip,
+                packets: element.packets,
+                bytes: element.bytes,
+                comment: element.comment,
+            })
+            .collect();
+
+        Ok(counters)
+    }
+}
+
+/// Keys of single element. Interval has keys of its start and end
+struct KernelElement {
+    keys: Vec<(IpAddr, u32)>,
+    timeout: Option<u32>,
+    comment: Option<String>,
+}
+
+/// Addresses covered by interval element, both inclusive
+struct Range {
+    start: IpAddr,
+    end: IpAddr,
+    timeout: Option<u32>,
+    comment: Option<String>,
+}
+
+/// Overlapping ranges are always merged as kernel rejects them.
+/// Merged range expires with the earliest of them, so nothing outlives its timeout.
+/// Adjacent ranges are merged only when nothing distinguishes them
+fn merge_ranges(ranges: Vec<Range>) -> Vec<Range> {
+    let mut merged: Vec<Range> = vec![];
+
+    for range in ranges {
+        if let Some(last) = merged.last_mut() {
+            let next = next_address(last.end);
+            let is_overlapping = range.start <= last.end;
+            let is_adjacent = next.map_or(true, |next| range.start <= next);
+            let is_same = range.timeout == last.timeout && range.comment == last.comment;
+
+            if is_overlapping || is_adjacent && is_same {
+                last.end = last.end.max(range.end);
+                last.timeout = match (last.timeout, range.timeout) {
+                    (Some(last), Some(timeout)) => Some(last.min(timeout)),
+                    (last, timeout) => last.or(timeout),
+                };
+                continue;
+            }
+        }
+
+        merged.push(range);
+    }
+
+    merged
+}
+
+fn address_bytes(address: IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(address) => address.octets().to_vec(),
+        IpAddr::V6(address) => address.octets().to_vec(),
+    }
+}
+
+fn next_address(address: IpAddr) -> Option<IpAddr> {
+    match address {
+        IpAddr::V4(address) => u32::from(address)
+            .checked_add(1)
+            .map(|next| Ipv4Addr::from(next).into()),
+        IpAddr::V6(address) => u128::from(address)
+            .checked_add(1)
+            .map(|next| Ipv6Addr::from(next).into()),
+    }
+}
+
+fn bytes_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+/// Collects keys with their flags from `NFT_MSG_NEWSETELEM` payload
+fn parse_elements(payload: &[u8], keys: &mut Vec<(IpAddr, u32)>) {
+    keys.extend(
+        dumped_elements(payload)
+            .into_iter()
+            .filter_map(|element| Some((element.key?, element.flags))),
+    );
+}
+
+/// Element as it is dumped by kernel
+#[derive(Default, Clone)]
+struct DumpedElement {
+    key: Option<IpAddr>,
+    flags: u32,
+    packets: u64,
+    bytes: u64,
+    comment: Option<String>,
+}
+
+fn dumped_elements(payload: &[u8]) -> Vec<DumpedElement> {
+    let Some(elements) = attributes(payload).find(|(kind, _)| *kind == NFTA_SET_ELEM_LIST_ELEMENTS)
+    else {
+        return vec![];
+    };
+
+    attributes(elements.1)
+        .filter(|(kind, _)| *kind == NFTA_LIST_ELEM)
+        .map(|(_, element)| {
+            let mut dumped = DumpedElement::default();
+
+            for (kind, value) in attributes(element) {
+                match kind {
+                    NFTA_SET_ELEM_KEY => {
+                        dumped.key = attributes(value)
+                            .find(|(kind, _)| *kind == NFTA_DATA_VALUE)
+                            .and_then(|(_, value)| bytes_address(value));
+                    }
+                    NFTA_SET_ELEM_FLAGS => {
+                        dumped.flags = <[u8; 4]>::try_from(value).map_or(0, u32::from_be_bytes);
+                    }
+                    NFTA_SET_ELEM_EXPR => parse_counter(value, &mut dumped),
+                    NFTA_SET_ELEM_EXPRESSIONS => {
+                        for (_, expression) in attributes(value) {
+                            parse_counter(expression, &mut dumped);
+                        }
+                    }
+                    NFTA_SET_ELEM_USERDATA => dumped.comment = parse_comment(value),
+                    _ => {}
+                }
+            }
+
+            dumped
+        })
+        .collect()
+}
+
+fn parse_counter(expression: &[u8], element: &mut DumpedElement) {
+    let is_counter = attributes(expression)
+        .any(|(kind, name)| kind == NFTA_EXPR_NAME && name.starts_with(b"counter\0"));
+    if !is_counter {
+        return;
+    }
+
+    for (_, data) in attributes(expression).filter(|(kind, _)| *kind == NFTA_EXPR_DATA) {
+        for (kind, value) in attributes(data) {
+            let value = <[u8; 8]>::try_from(value).map_or(0, u64::from_be_bytes);
+            match kind {
+                NFTA_COUNTER_BYTES => element.bytes = value,
+                NFTA_COUNTER_PACKETS => element.packets = value,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Userdata is a sequence of type, length and value records
+fn parse_comment(mut userdata: &[u8]) -> Option<String> {
+    while let [kind, len, rest @ ..] = userdata {
+        let value = rest.get(..usize::from(*len))?;
+        if *kind == NFTNL_UDATA_SET_ELEM_COMMENT {
+            let value = value.strip_suffix(&[0]).unwrap_or(value);
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+        userdata = &rest[value.len()..];
+    }
+
+    None
+}
+
+fn keys_to_entries(keys: Vec<(IpAddr, u32)>, is_interval: bool) -> Vec<IP> {
+    let keys = keys
+        .into_iter()
+        .map(|(key, flags)| (key, flags, ()))
+        .collect();
+
+    join_keys(keys, is_interval)
+        .into_iter()
+        .map(|(entry, ())| entry)
+        .collect()
+}
+
+/// Joins interval starts with their ends.
+/// Subnets of the interval get data of its start
+fn join_keys<T: Clone>(mut keys: Vec<(IpAddr, u32, T)>, is_interval: bool) -> Vec<(IP, T)> {
+    if !is_interval {
+        return keys
+            .into_iter()
+            .map(|(key, _, data)| (IP::Single(key), data))
+            .collect();
+    }
+
+    // End of one interval may equal to start of another, so ends go first
+    keys.sort_unstable_by_key(|(key, flags, _)| (*key, *flags & NFT_SET_ELEM_INTERVAL_END == 0));
+
+    let mut entries = vec![];
+    let mut starts = keys.iter().peekable();
+
+    while let Some((start, flags, data)) = starts.next() {
+        if flags & NFT_SET_ELEM_INTERVAL_END != 0 {
+            continue;
+        }
+
+        let end = match starts.peek() {
+            Some((end, flags, _)) if flags & NFT_SET_ELEM_INTERVAL_END != 0 => {
+                starts.next();
+                previous_address(*end)
+            }
+            // Interval lasts till the end of address space
+            _ => last_address(*start),
+        };
+
+        match (start, end) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => entries.extend(
+                Ipv4Subnets::new(*start, end, 0)
+                    .map(|subnet| (IP::Network(subnet.into()), data.clone())),
+            ),
+            (IpAddr::V6(start), IpAddr::V6(end)) => entries.extend(
+                Ipv6Subnets::new(*start, end, 0)
+                    .map(|subnet| (IP::Network(subnet.into()), data.clone())),
+            ),
+            _ => {}
+        }
+    }
+
+    entries
+}
+
+fn previous_address(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => Ipv4Addr::from(u32::from(address).saturating_sub(1)).into(),
+        IpAddr::V6(address) => Ipv6Addr::from(u128::from(address).saturating_sub(1)).into(),
+    }
+}
+
+fn last_address(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V4(_) => Ipv4Addr::BROADCAST.into(),
+        IpAddr::V6(_) => Ipv6Addr::from(u128::MAX).into(),
+    }
+}
+
+/// Iterates over netlink attributes yielding their types and payloads
+fn attributes(mut buffer: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let header = buffer.get(..4)?;
+        let len = usize::from(u16::from_ne_bytes([header[0], header[1]]));
+        let kind = u16::from_ne_bytes([header[2], header[3]]) & NLA_TYPE_MASK;
+
+        let payload = buffer.get(4..len)?;
+        buffer = buffer.get(align(len)..).unwrap_or_default();
+
+        Some((kind, payload))
+    })
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// `NFT_MSG_NEWSETELEM` being filled with elements
+struct ElementsMessage {
+    message: Message,
+    start: usize,
+    elements: usize,
+}
+
+impl ElementsMessage {
+    fn new(set: &NetlinkSet) -> Result<Self> {
+        let mut message = Message::default();
+        let start =
+            set.begin_set_message(&mut message, NFT_MSG_NEWSETELEM, NLM_F_CREATE | NLM_F_ACK)?;
+        let elements = message.nest_start(NFTA_SET_ELEM_LIST_ELEMENTS)?;
+
+        Ok(Self {
+            message,
+            start,
+            elements,
+        })
+    }
+
+    fn put_element(
+        &mut self,
+        key: IpAddr,
+        flags: u32,
+        timeout: Option<u32>,
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let message = &mut self.message;
+
+        let element = message.nest_start(NFTA_LIST_ELEM)?;
+        let key_start = message.nest_start(NFTA_SET_ELEM_KEY)?;
+        message.put(NFTA_DATA_VALUE, &address_bytes(key))?;
+        message.nest_end(key_start)?;
+
+        if flags != 0 {
+            message.put(NFTA_SET_ELEM_FLAGS, &flags.to_be_bytes())?;
+        }
+
+        if let Some(timeout) = timeout {
+            let milliseconds = u64::from(timeout) * 1000;
+            message.put(NFTA_SET_ELEM_TIMEOUT, &milliseconds.to_be_bytes())?;
+        }
+
+        if let Some(comment) = comment {
+            // Record length is a single byte including trailing zero
+            let comment = &comment.as_bytes()[..comment.len().min(254)];
+            let length = u8::try_from(comment.len() + 1).unwrap_or(u8::MAX);
+            let mut userdata = vec![NFTNL_UDATA_SET_ELEM_COMMENT, length];
+            userdata.extend_from_slice(comment);
+            userdata.push(0);
+            message.put(NFTA_SET_ELEM_USERDATA, &userdata)?;
+        }
+
+        message.nest_end(element)
+    }
+
+    fn finish(mut self) -> Result<Message> {
+        self.message.nest_end(self.elements)?;
+        self.message.end(self.start)?;
+        Ok(self.message)
+    }
+}
+
+#[derive(Default)]
+struct Message {
+    buffer: Vec<u8>,
+}
+
+impl Message {
+    fn begin(&mut self, msg_type: u16, flags: u16, family: u8) -> usize {
+        let start = self.buffer.len();
+
+        // nlmsghdr. Length is filled when message ends
+        self.buffer.extend(0_u32.to_ne_bytes());
+        self.buffer
+            .extend(((NFNL_SUBSYS_NFTABLES << 8) | msg_type).to_ne_bytes());
+        self.buffer.extend((NLM_F_REQUEST | flags).to_ne_bytes());
+        self.buffer.extend(0_u32.to_ne_bytes());
+        self.buffer.extend(0_u32.to_ne_bytes());
+
+        // nfgenmsg
+        self.buffer.extend([family, 0]);
+        self.buffer.extend(0_u16.to_be_bytes());
+
+        start
+    }
+
+    fn begin_batch(&mut self, msg_type: u16) -> usize {
+        let start = self.buffer.len();
+
+        self.buffer.extend(0_u32.to_ne_bytes());
+        self.buffer.extend(msg_type.to_ne_bytes());
+        self.buffer.extend(NLM_F_REQUEST.to_ne_bytes());
+        self.buffer.extend(0_u32.to_ne_bytes());
+        self.buffer.extend(0_u32.to_ne_bytes());
+
+        self.buffer.extend([0, 0]);
+        self.buffer.extend(NFNL_SUBSYS_NFTABLES.to_be_bytes());
+
+        start
+    }
+
+    fn end(&mut self, start: usize) -> Result<()> {
+        let len = self.buffer.len() - start;
+        let len = u32::try_from(len).map_err(|_| anyhow!("Message of {len} bytes is too long"))?;
+        self.buffer[start..start + 4].copy_from_slice(&len.to_ne_bytes());
+        Ok(())
+    }
+
+    fn put(&mut self, kind: u16, payload: &[u8]) -> Result<()> {
+        let len = attribute_len(4 + payload.len())?;
+        self.buffer.extend(len.to_ne_bytes());
+        self.buffer.extend(kind.to_ne_bytes());
+        self.buffer.extend(payload);
+        self.buffer.resize(align(self.buffer.len()), 0);
+        Ok(())
+    }
+
+    fn put_str(&mut self, kind: u16, value: &str) -> Result<()> {
+        let mut payload = value.as_bytes().to_vec();
+        payload.push(0);
+        self.put(kind, &payload)
+    }
+
+    fn nest_start(&mut self, kind: u16) -> Result<usize> {
+        let start = self.buffer.len();
+        self.put(kind | NLA_F_NESTED, &[])?;
+        Ok(start)
+    }
+
+    fn nest_end(&mut self, start: usize) -> Result<()> {
+        let len = attribute_len(self.buffer.len() - start)?;
+        self.buffer[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        Ok(())
+    }
+}
+
+/// Truncated length would corrupt the whole message, so it is an error
+fn attribute_len(len: usize) -> Result<u16> {
+    u16::try_from(len).map_err(|_| anyhow!("Netlink attribute of {len} bytes is too long"))
+}
+
+struct Socket(OwnedFd);
+
+impl Socket {
+    fn open() -> Result<Self> {
+        // SAFETY: plain syscall, descriptor is owned right after the check
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                NETLINK_NETFILTER,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // SAFETY: descriptor is valid and not owned by anything else
+        let socket = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        // Never wait for kernel forever
+        let timeout = libc::timeval {
+            tv_sec: 10,
+            tv_usec: 0,
+        };
+
+        // SAFETY: pointer and size describe valid timeval
+        let result = unsafe {
+            libc::setsockopt(
+                socket.0.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                std::ptr::addr_of!(timeout).cast(),
+                u32::try_from(size_of::<libc::timeval>())?,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(socket)
+    }
+
+    fn send_batch(&self, messages: &[Message]) -> Result<()> {
+        let mut batch = Message::default();
+        let start = batch.begin_batch(NFNL_MSG_BATCH_BEGIN);
+        batch.end(start)?;
+
+        for message in messages {
+            batch.buffer.extend(&message.buffer);
+        }
+
+        let start = batch.begin_batch(NFNL_MSG_BATCH_END);
+        batch.end(start)?;
+
+        self.send(&batch.buffer)?;
+        self.receive_acks(messages.len())
+    }
+
+    fn send(&self, buffer: &[u8]) -> Result<()> {
+        // SAFETY: buffer is valid for its length
+        let sent =
+            unsafe { libc::send(self.0.as_raw_fd(), buffer.as_ptr().cast(), buffer.len(), 0) };
+        if sent < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    /// Calls `handle` with payload of every received message until the end is reached
+    fn receive(&self, mut handle: impl FnMut(u16, &[u8]) -> Result<bool>) -> Result<()> {
+        let mut buffer = vec![0_u8; 256 * 1024];
+
+        loop {
+            // SAFETY: buffer is valid for its length
+            let received = unsafe {
+                libc::recv(
+                    self.0.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                    0,
+                )
+            };
+            let received = usize::try_from(received).map_err(|_| io::Error::last_os_error())?;
+
+            let mut messages = &buffer[..received];
+            while messages.len() >= HEADERS_LEN {
+                let len = u32::from_ne_bytes([messages[0], messages[1], messages[2], messages[3]]);
+                let len = usize::try_from(len)?.clamp(16, messages.len());
+                let msg_type = u16::from_ne_bytes([messages[4], messages[5]]);
+
+                if !handle(msg_type, &messages[16..len])? {
+                    return Ok(());
+                }
+
+                messages = &messages[align(len).min(messages.len())..];
+            }
+        }
+    }
+
+    fn receive_acks(&self, mut expected: usize) -> Result<()> {
+        if expected == 0 {
+            return Ok(());
+        }
+
+        self.receive(|msg_type, payload| {
+            if msg_type == NLMSG_ERROR {
+                check_error(payload)?;
+                expected -= 1;
+            }
+
+            Ok(expected > 0)
+        })
+    }
+
+    fn receive_dump(&self, mut handle: impl FnMut(&[u8])) -> Result<()> {
+        self.receive(|msg_type, payload| match msg_type {
+            NLMSG_DONE => Ok(false),
+            NLMSG_ERROR => check_error(payload).map(|()| false),
+            _ => {
+                // Skip nfgenmsg
+                handle(payload.get(4..).unwrap_or_default());
+                Ok(true)
+            }
+        })
+    }
+}
+
+fn check_error(payload: &[u8]) -> Result<()> {
+    let code = payload
+        .get(..4)
+        .and_then(|code| <[u8; 4]>::try_from(code).ok())
+        .map_or(0, i32::from_ne_bytes);
+
+    if code == 0 {
+        return Ok(());
+    }
+
+    let error = io::Error::from_raw_os_error(-code);
+    Err(anyhow!("nf_tables rejected request: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dumped_elements, join_keys, keys_to_entries, merge_ranges, parse_elements, ElementsMessage,
+        Message, NetlinkSet, Range, HEADERS_LEN, NFTNL_UDATA_SET_ELEM_COMMENT,
+        NFT_SET_ELEM_INTERVAL_END,
+    };
+    use crate::source::SetTemplate;
+    use std::net::IpAddr;
+
+    fn address(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn join_intervals() {
+        let keys = vec![
+            (address("10.0.1.0"), NFT_SET_ELEM_INTERVAL_END),
+            (address("10.0.0.0"), 0),
+            (address("10.0.1.0"), 0),
+            (address("10.0.1.2"), NFT_SET_ELEM_INTERVAL_END),
+            (address("255.255.255.255"), 0),
+        ];
+
+        let entries: Vec<String> = keys_to_entries(keys, true)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            entries,
+            ["10.0.0.0/24", "10.0.1.0/31", "255.255.255.255/32"]
+        );
+    }
+
+    #[test]
+    fn parse_encoded_elements() {
+        let mut message = Message::default();
+        let elements = message.nest_start(3).unwrap();
+        let element = message.nest_start(1).unwrap();
+        let key = message.nest_start(1).unwrap();
+        message.put(1, &[1, 1, 1, 1]).unwrap();
+        message.nest_end(key).unwrap();
+        message
+            .put(3, &NFT_SET_ELEM_INTERVAL_END.to_be_bytes())
+            .unwrap();
+        message.nest_end(element).unwrap();
+        message.nest_end(elements).unwrap();
+
+        let mut keys = vec![];
+        parse_elements(&message.buffer, &mut keys);
+
+        assert_eq!(keys, [(address("1.1.1.1"), NFT_SET_ELEM_INTERVAL_END)]);
+    }
+
+    #[test]
+    fn merge_overlapping_ranges() {
+        let range = |start: &str, end: &str, timeout: Option<u32>, comment: &str| Range {
+            start: address(start),
+            end: address(end),
+            timeout,
+            comment: Some(comment.to_string()),
+        };
+
+        let merged = merge_ranges(vec![
+            range("10.0.0.0", "10.0.0.255", Some(600), "a"),
+            range("10.0.0.128", "10.0.1.255", None, "b"),
+            range("10.0.1.200", "10.0.1.210", Some(60), "b"),
+            // Adjacent, but expires separately
+            range("10.0.2.0", "10.0.2.255", Some(600), "a"),
+            // Adjacent and same
+            range("10.0.3.0", "10.0.3.255", Some(600), "a"),
+            // Adjacent, but from another origin
+            range("10.0.4.0", "10.0.4.255", Some(600), "c"),
+        ]);
+
+        let merged: Vec<_> = merged
+            .iter()
+            .map(|range| (range.start, range.end, range.timeout))
+            .collect();
+
+        assert_eq!(
+            merged,
+            [
+                (address("10.0.0.0"), address("10.0.1.255"), Some(60)),
+                (address("10.0.2.0"), address("10.0.3.255"), Some(600)),
+                (address("10.0.4.0"), address("10.0.4.255"), Some(600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn join_keys_with_data() {
+        let keys = vec![
+            (address("10.0.0.0"), 0, "a"),
+            (address("10.0.0.3"), NFT_SET_ELEM_INTERVAL_END, "end"),
+            (address("10.0.0.3"), 0, "b"),
+            (address("10.0.0.4"), NFT_SET_ELEM_INTERVAL_END, "end"),
+        ];
+
+        let joined: Vec<_> = join_keys(keys, true)
+            .into_iter()
+            .map(|(ip, data)| (ip.to_string(), data))
+            .collect();
+
+        assert_eq!(
+            joined,
+            [
+                ("10.0.0.0/31".to_string(), "a"),
+                ("10.0.0.2/32".to_string(), "a"),
+                ("10.0.0.3/32".to_string(), "b"),
+            ]
+        );
+
+        let single = join_keys(vec![(address("1.1.1.1"), 0, 7)], false);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].1, 7);
+    }
+
+    #[test]
+    fn encode_element_comment() {
+        let set = NetlinkSet::with_template("blocked", "fw4", &SetTemplate::default());
+        let mut message = ElementsMessage::new(&set).unwrap();
+        message
+            .put_element(address("1.1.1.1"), 0, None, Some("list"))
+            .unwrap();
+        let message = message.finish().unwrap();
+
+        // Comment record is type, length with trailing zero and value
+        let record = [NFTNL_UDATA_SET_ELEM_COMMENT, 5, b'l', b'i', b's', b't', 0];
+        assert!(message
+            .buffer
+            .windows(record.len())
+            .any(|window| window == record));
+
+        let elements = dumped_elements(&message.buffer[HEADERS_LEN..]);
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].key, Some(address("1.1.1.1")));
+        assert_eq!(elements[0].comment.as_deref(), Some("list"));
+    }
+}
@@ -0,0 +1,106 @@
+use super::{ElementCounter, SetBackend};
+use crate::{
+    nf_helpers::RoutingOptions,
+    source::{SetElement, IP},
+};
+use anyhow::{anyhow, Result};
+use std::{
+    fs::File,
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// Directory where `ip netns add` keeps named namespaces
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+/// Runs operations of wrapped set inside another network namespace.
+/// Namespace is entered by a short-lived thread, so the rest of process is not affected
+pub(crate) struct NetnsSet {
+    path: PathBuf,
+    inner: Box<dyn SetBackend>,
+}
+
+impl NetnsSet {
+    pub(crate) fn new(netns: &str, inner: Box<dyn SetBackend>) -> Self {
+        Self {
+            path: netns_path(netns),
+            inner,
+        }
+    }
+
+    fn run<T: Send>(
+        &self,
+        operation: impl FnOnce(&dyn SetBackend) -> Result<T> + Send,
+    ) -> Result<T> {
+        run_in_netns(&self.path, || operation(self.inner.as_ref()))
+    }
+}
+
+/// Namespace is either a name or a path like `/proc/1234/ns/net`
+pub(crate) fn netns_path(netns: &str) -> PathBuf {
+    if netns.contains('/') {
+        PathBuf::from(netns)
+    } else {
+        Path::new(NETNS_RUN_DIR).join(netns)
+    }
+}
+
+/// Calls operation from a thread which entered the namespace
+pub(crate) fn run_in_netns<T: Send>(
+    path: &Path,
+    operation: impl FnOnce() -> Result<T> + Send,
+) -> Result<T> {
+    let netns = File::open(path)
+        .map_err(|error| anyhow!("Cannot open network namespace {}: {error}", path.display()))?;
+
+    std::thread::scope(|scope| {
+        let thread = scope.spawn(|| {
+            // SAFETY: descriptor is kept open by `netns` during the call
+            let result = unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) };
+            if result != 0 {
+                return Err(anyhow!(
+                    "Cannot enter network namespace {}: {}",
+                    path.display(),
+                    io::Error::last_os_error()
+                ));
+            }
+
+            operation()
+        });
+
+        thread
+            .join()
+            .map_err(|_| anyhow!("Thread in network namespace panicked"))?
+    })
+}
+
+impl SetBackend for NetnsSet {
+    fn flush(&self) -> Result<()> {
+        self.run(|set| set.flush())
+    }
+
+    fn load_entries(&self, entries: Vec<SetElement>, chunk_size: usize) -> Result<()> {
+        self.run(|set| set.load_entries(entries, chunk_size))
+    }
+
+    fn replace_entries(&self, entries: Vec<SetElement>, chunk_size: usize) -> Result<()> {
+        self.run(|set| set.replace_entries(entries, chunk_size))
+    }
+
+    fn list_entries(&self) -> Result<Vec<IP>> {
+        self.run(|set| set.list_entries())
+    }
+
+    fn list_counters(&self) -> Result<Vec<ElementCounter>> {
+        self.run(|set| set.list_counters())
+    }
+
+    fn apply_routing(&self, routing: &RoutingOptions) -> Result<()> {
+        self.run(|set| set.apply_routing(routing))
+    }
+
+    fn remove_routing(&self, routing: &RoutingOptions) -> Result<()> {
+        self.run(|set| set.remove_routing(routing))
+    }
+}
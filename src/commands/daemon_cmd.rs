@@ -1,12 +1,12 @@
 use super::{
-    update_cmd::{Command as UpdateCommand, UpdateRequestBuilder},
+    update_cmd::{Command as UpdateCommand, UpdateRequest, UpdateRequestBuilder},
     CliCommand, GlobalOptions,
 };
-use crate::config::Config;
 use anyhow::{anyhow, Result};
 use chrono::Local;
 use job_scheduler_ng::{Job, JobScheduler, Schedule};
 use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
 
 #[derive(clap::Parser)]
 pub(crate) struct Command {
@@ -15,15 +15,15 @@ pub(crate) struct Command {
 }
 
 impl Command {
-    async fn construct_update_job(&self, config: Config) -> Result<Job> {
-        let schedule: Schedule = match &config.update_schedule {
+    fn construct_update_job(
+        command: Arc<UpdateCommand>,
+        request: Arc<UpdateRequest>,
+    ) -> Result<Job> {
+        let schedule: Schedule = match &request.config.update_schedule {
             Some(schedule) => schedule.parse()?,
             None => return Err(anyhow!("Schedule must be presented for running as daemon!")),
         };
 
-        let command = Arc::new(UpdateCommand::new(self.global_options.clone()));
-        let request = Arc::new(UpdateRequestBuilder::new(config).build().await?);
-
         let job = Job::new(schedule, move || {
             let command = command.clone();
             let request = request.clone();
@@ -37,6 +37,27 @@ impl Command {
 
         Ok(job)
     }
+
+    /// Keeps sets filled between updates, e.g. after firewall reloads
+    fn spawn_repopulate_task(
+        command: Arc<UpdateCommand>,
+        request: Arc<UpdateRequest>,
+    ) -> Option<JoinHandle<()>> {
+        let period = request.config.repopulate_interval?;
+
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+
+                if let Err(error) = command.repopulate(&request).await {
+                    log::error!("Error when repopulating sets: {error:?}");
+                }
+            }
+        });
+
+        Some(task)
+    }
 }
 
 #[async_trait]
@@ -51,8 +72,12 @@ impl CliCommand for Command {
         scheduler.set_timezone(timezone);
 
         let config = self.global_options.parse_config()?;
-        let update_job = self.construct_update_job(config).await?;
+        let command = Arc::new(UpdateCommand::new(self.global_options.clone()));
+        let request = Arc::new(UpdateRequestBuilder::new(config).build().await?);
+
+        let update_job = Self::construct_update_job(command.clone(), request.clone())?;
         let update_job_uuid = scheduler.add(update_job);
+        let repopulate_task = Self::spawn_repopulate_task(command, request);
 
         let shutdown = tokio_shutdown::Shutdown::new()?;
         loop {
@@ -60,6 +85,9 @@ impl CliCommand for Command {
                 () = shutdown.handle() => {
                     log::warn!("Got shutdown signal. Exiting...");
                     scheduler.remove(update_job_uuid);
+                    if let Some(task) = &repopulate_task {
+                        task.abort();
+                    }
                     break;
                 },
                 () = tokio::time::sleep(Duration::from_millis(500)) => {
@@ -0,0 +1,309 @@
+use super::{update_cmd::UpdateRequestBuilder, CliCommand, GlobalOptions};
+use crate::{
+    backend::{Backend, IpSet},
+    dnsmasq::{render_config, DnsmasqSet},
+    nf_helpers::{keyword, properties},
+    source::{Source, IP},
+};
+use anyhow::{anyhow, Result};
+use std::{collections::HashSet, fmt::Write, fs, path::PathBuf};
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    /// One address or subnet per line
+    Plain,
+    /// `network,prefix_len` rows with header
+    Csv,
+    /// Array of strings
+    Json,
+    /// Script for `nft -f`
+    Nft,
+    /// Script for `ipset restore`
+    Ipset,
+    /// Static routes for bird2
+    Bird,
+    /// Routes for `ip -batch`
+    Route,
+    /// `nftset` directives for domain sources
+    Dnsmasq,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Plain => "txt",
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Nft => "nft",
+            Self::Ipset => "ipset",
+            Self::Bird | Self::Dnsmasq => "conf",
+            Self::Route => "route",
+        }
+    }
+}
+
+/// Writes merged set contents in format suitable for other software
+#[derive(clap::Parser)]
+pub(crate) struct Command {
+    #[clap(flatten)]
+    global_options: GlobalOptions,
+
+    #[arg(long, value_enum, default_value = "plain")]
+    format: ExportFormat,
+
+    /// Sets to export. All configured sets by default
+    #[arg(long = "set")]
+    sets: Vec<String>,
+
+    /// Directory for `<set>.<format>` files. Stdout by default
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Read sets from kernel instead of downloading lists
+    #[arg(long)]
+    live: bool,
+
+    /// Route parameters after the network, e.g. `dev wg0`
+    #[arg(long, required_if_eq("format", "route"))]
+    route_args: Option<String>,
+}
+
+impl Command {
+    fn render(&self, source: &Source, table_name: &str, entries: &[IP]) -> String {
+        let set_name = &source.set_name;
+
+        let mut rendered = String::new();
+
+        match self.format {
+            ExportFormat::Plain => {
+                for entry in entries {
+                    let _ = writeln!(rendered, "{entry}");
+                }
+            }
+            ExportFormat::Csv => {
+                rendered.push_str("network,prefix_len\n");
+                for network in entries.iter().map(IP::to_network) {
+                    let _ = writeln!(rendered, "{},{}", network.network(), network.prefix_len());
+                }
+            }
+            ExportFormat::Json => {
+                let entries: Vec<String> = entries.iter().map(ToString::to_string).collect();
+                rendered = serde_json::to_string_pretty(&entries).unwrap_or_default();
+                rendered.push('\n');
+            }
+            ExportFormat::Nft => {
+                let family = keyword(&source.set_template.family);
+                let properties = properties(&source.set_template, None).join("; ");
+                let _ = writeln!(rendered, "add table {family} {table_name}");
+                let _ = writeln!(
+                    rendered,
+                    "add set {family} {table_name} {set_name} {{ {properties}; }}"
+                );
+                let _ = writeln!(rendered, "flush set {family} {table_name} {set_name}");
+                for entry in entries {
+                    let _ = writeln!(
+                        rendered,
+                        "add element {family} {table_name} {set_name} {{ {entry} }}"
+                    );
+                }
+            }
+            ExportFormat::Ipset => {
+                let set = IpSet::with_template(set_name, &source.set_template);
+                rendered = set.create_command(set_name, Some(entries.len()));
+                let _ = writeln!(rendered, "flush {set_name}");
+                for entry in entries {
+                    let _ = writeln!(rendered, "add {set_name} {entry} -exist");
+                }
+            }
+            ExportFormat::Bird => {
+                // Routes with host bits set are rejected
+                for network in entries.iter().map(|entry| entry.to_network().trunc()) {
+                    let _ = writeln!(rendered, "route {network} unreachable;");
+                }
+            }
+            ExportFormat::Route => {
+                let route_args = self.route_args.as_deref().unwrap_or_default();
+                for network in entries.iter().map(|entry| entry.to_network().trunc()) {
+                    let _ = writeln!(rendered, "route replace {network} {route_args}");
+                }
+            }
+            ExportFormat::Dnsmasq => {}
+        }
+
+        rendered
+    }
+
+    fn render_domains(
+        source: &Source,
+        backend: Backend,
+        table_name: &str,
+        domains: HashSet<String>,
+    ) -> String {
+        let set = DnsmasqSet {
+            set_name: &source.set_name,
+            template: &source.set_template,
+            domains,
+        };
+
+        render_config(backend, table_name, &[set])
+    }
+
+    fn write(&self, set_name: &str, rendered: &str) -> Result<()> {
+        let Some(output) = &self.output else {
+            print!("{rendered}");
+            return Ok(());
+        };
+
+        let path = output.join(format!("{set_name}.{}", self.format.extension()));
+        fs::write(&path, rendered)?;
+        log::info!("Set {set_name} is exported to {}", path.display());
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CliCommand for Command {
+    async fn run(&self) -> Result<()> {
+        let config = self.global_options.parse_config()?;
+        let request = UpdateRequestBuilder::new(config).build().await?;
+        let table_name = &request.config.table_name;
+
+        for set_name in &self.sets {
+            if !request
+                .config
+                .sources
+                .iter()
+                .any(|source| &source.set_name == set_name)
+            {
+                return Err(anyhow!("Set {set_name} is missing in config"));
+            }
+        }
+
+        let sources = request
+            .config
+            .sources
+            .iter()
+            .filter(|source| self.sets.is_empty() || self.sets.contains(&source.set_name));
+
+        for source in sources {
+            if self.format == ExportFormat::Dnsmasq {
+                // Same sources as update hands to dnsmasq
+                if !source.dnsmasq || self.live {
+                    log::warn!("Set {} is not managed by dnsmasq", source.set_name);
+                    continue;
+                }
+
+                let domains = source.download_domains(request.fetch_context()).await?;
+                let rendered =
+                    Self::render_domains(source, request.config.backend, table_name, domains);
+                self.write(&source.set_name, &rendered)?;
+                continue;
+            }
+
+            let entries = if self.live {
+                let set = request.config.open_set(source);
+                set.list_entries()?
+            } else {
+                let elements = source.download_elements(request.fetch_context()).await?;
+                elements.into_iter().map(|element| element.ip).collect()
+            };
+
+            self.write(&source.set_name, &self.render(source, table_name, &entries))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Command;
+    use crate::{backend::Backend, source::Source};
+    use clap::Parser;
+    use std::collections::HashSet;
+
+    fn render(args: &[&str], set_type: &str) -> String {
+        let command = Command::parse_from([&["export"], args].concat());
+        let source: Source = serde_yaml::from_str(&format!(
+            "{{set_name: blocked, entries_limit: 0, set_template: {{type: {set_type}}}}}"
+        ))
+        .unwrap();
+        let entries = ["1.1.1.1".parse().unwrap(), "10.1.2.3/8".parse().unwrap()];
+
+        command.render(&source, "hirkn", &entries)
+    }
+
+    #[test]
+    fn render_plain() {
+        assert_eq!(render(&[], "ipv4_addr"), "1.1.1.1\n10.1.2.3/8\n");
+    }
+
+    #[test]
+    fn render_csv() {
+        assert_eq!(
+            render(&["--format", "csv"], "ipv4_addr"),
+            "network,prefix_len\n1.1.1.1,32\n10.0.0.0,8\n"
+        );
+    }
+
+    #[test]
+    fn render_json() {
+        assert_eq!(
+            render(&["--format", "json"], "ipv4_addr"),
+            "[\n  \"1.1.1.1\",\n  \"10.1.2.3/8\"\n]\n"
+        );
+    }
+
+    #[test]
+    fn render_nft() {
+        let expected = "add table inet hirkn\n\
+            add set inet hirkn blocked { type ipv4_addr; flags interval; }\n\
+            flush set inet hirkn blocked\n\
+            add element inet hirkn blocked { 1.1.1.1 }\n\
+            add element inet hirkn blocked { 10.1.2.3/8 }\n";
+
+        assert_eq!(render(&["--format", "nft"], "ipv4_addr"), expected);
+    }
+
+    #[test]
+    fn render_ipset() {
+        let expected = "create blocked hash:net family inet6 maxelem 65536 -exist\n\
+            flush blocked\n\
+            add blocked 1.1.1.1 -exist\n\
+            add blocked 10.1.2.3/8 -exist\n";
+
+        assert_eq!(render(&["--format", "ipset"], "ipv6_addr"), expected);
+    }
+
+    #[test]
+    fn render_bird() {
+        assert_eq!(
+            render(&["--format", "bird"], "ipv4_addr"),
+            "route 1.1.1.1/32 unreachable;\nroute 10.0.0.0/8 unreachable;\n"
+        );
+    }
+
+    #[test]
+    fn render_route() {
+        assert_eq!(
+            render(
+                &["--format", "route", "--route-args", "dev wg0"],
+                "ipv4_addr"
+            ),
+            "route replace 1.1.1.1/32 dev wg0\nroute replace 10.0.0.0/8 dev wg0\n"
+        );
+    }
+
+    #[test]
+    fn render_dnsmasq() {
+        let source: Source =
+            serde_yaml::from_str("{set_name: blocked, entries_limit: 0, dnsmasq: true}").unwrap();
+        let domains = HashSet::from(["example.com".to_string()]);
+
+        assert_eq!(
+            Command::render_domains(&source, Backend::Nftables, "hirkn", domains),
+            "# blocked\nnftset=/example.com/4#inet#hirkn#blocked\n"
+        );
+    }
+}
@@ -1,5 +1,4 @@
 use super::{CliCommand, GlobalOptions};
-use crate::nf_helpers::NfSet;
 use anyhow::Result;
 
 #[derive(clap::Parser)]
@@ -15,17 +14,27 @@ impl CliCommand for Command {
 
         let mut success = true;
 
-        for source in &config.sources {
-            let nfset = NfSet::with_template(
-                &source.set_name,
-                &config.table_name,
-                source.set_template.clone(),
-            );
+        for map in &config.maps {
+            if let Err(error) = config.open_map(map).and_then(|map| map.flush()) {
+                success = false;
+                log::error!("Error while flushing: {error:?}");
+            }
+        }
+
+        for source in config.sources.iter().filter(|source| source.map.is_none()) {
+            let set = config.open_set(source);
+
+            if let Some(routing) = &source.routing {
+                if let Err(error) = set.remove_routing(routing) {
+                    success = false;
+                    log::error!("Error while removing routing: {error:?}");
+                }
+            }
 
-            if let Err(error) = nfset.flush() {
+            if let Err(error) = set.flush() {
                 success = false;
                 log::error!("Error while flushing: {error:?}");
-            };
+            }
         }
 
         if success {
@@ -0,0 +1,76 @@
+use super::{update_cmd::UpdateRequestBuilder, CliCommand, GlobalOptions};
+use anyhow::{anyhow, Result};
+use std::{fs, io::Read, path::PathBuf};
+
+/// Loads list into configured set bypassing its urls
+#[derive(clap::Parser)]
+pub(crate) struct Command {
+    #[clap(flatten)]
+    global_options: GlobalOptions,
+
+    /// Name of the set from config
+    #[arg(long)]
+    set: String,
+
+    /// List to load. `-` for stdin
+    #[arg(default_value = "-")]
+    input: PathBuf,
+
+    /// Add entries to the set instead of replacing its contents
+    #[arg(long)]
+    append: bool,
+}
+
+impl Command {
+    fn read_input(&self) -> Result<String> {
+        if self.input.as_os_str() != "-" {
+            return Ok(fs::read_to_string(&self.input)?);
+        }
+
+        let mut raw_list = String::new();
+        std::io::stdin().read_to_string(&mut raw_list)?;
+        Ok(raw_list)
+    }
+}
+
+#[async_trait]
+impl CliCommand for Command {
+    async fn run(&self) -> Result<()> {
+        let config = self.global_options.parse_config()?;
+        let raw_list = self.read_input()?;
+
+        let request = UpdateRequestBuilder::new(config).build().await?;
+
+        let source = request
+            .config
+            .sources
+            .iter()
+            .find(|source| source.set_name == self.set)
+            .ok_or_else(|| anyhow!("Set {} is missing in config", self.set))?;
+
+        if let Some(target) = &source.map {
+            return Err(anyhow!(
+                "Set {} fills {} map and cannot be loaded",
+                self.set,
+                target.name
+            ));
+        }
+
+        let entries = source.parse_list(&raw_list, request.fetch_context());
+        if entries.is_empty() {
+            log::warn!("Nothing to load into {} set", self.set);
+            return Ok(());
+        }
+
+        let set = request.config.open_set(source);
+
+        log::info!("Loading {} entries into {} set", entries.len(), self.set);
+        if self.append {
+            set.load_entries(entries, request.chunk_size())?;
+        } else {
+            set.replace_entries(entries, request.chunk_size())?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 mod daemon_cmd;
+mod export_cmd;
 mod flush_cmd;
+mod load_cmd;
+mod stats_cmd;
 mod update_cmd;
 
 use crate::config::Config;
@@ -33,10 +36,11 @@ impl GlobalOptions {
 
         if is_default_config_missing {
             fs::copy(EXAMPLE_CONFIG_PATH, DEFAULT_CONFIG_PATH)?;
-        };
+        }
 
-        let config = Config::from_file(&self.config)?;
+        let mut config = Config::from_file(&self.config)?;
         config.init_logger();
+        config.validate()?;
 
         Ok(config)
     }
@@ -49,4 +53,7 @@ pub(crate) enum Command {
     Update(update_cmd::Command),
     RunDaemon(daemon_cmd::Command),
     Flush(flush_cmd::Command),
+    Load(load_cmd::Command),
+    Export(export_cmd::Command),
+    Stats(stats_cmd::Command),
 }
@@ -0,0 +1,117 @@
+use super::{update_cmd::UpdateRequestBuilder, CliCommand, GlobalOptions};
+use crate::{backend::ElementCounter, source::SetElement};
+use anyhow::{anyhow, Result};
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum StatsOrder {
+    Packets,
+    Bytes,
+}
+
+/// Shows elements of live sets which matched the most traffic.
+/// Sets must be created with `counter` in their template
+#[derive(clap::Parser)]
+pub(crate) struct Command {
+    #[clap(flatten)]
+    global_options: GlobalOptions,
+
+    /// Sets to report. All configured sets by default
+    #[arg(long = "set")]
+    sets: Vec<String>,
+
+    /// Number of elements shown for each set
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+
+    #[arg(long, value_enum, default_value = "packets")]
+    by: StatsOrder,
+}
+
+/// Finds list which element came from. Kernel may merge adjacent
+/// networks, so containment is checked both ways
+fn find_origin<'a>(counter: &ElementCounter, origins: &'a [SetElement]) -> Option<&'a str> {
+    let network = counter.ip.to_network();
+
+    origins
+        .iter()
+        .find(|origin| {
+            let origin = origin.ip.to_network();
+            origin.contains(&network) || network.contains(&origin)
+        })
+        .and_then(|origin| origin.comment.as_deref())
+}
+
+#[async_trait]
+impl CliCommand for Command {
+    async fn run(&self) -> Result<()> {
+        let config = self.global_options.parse_config()?;
+        let request = UpdateRequestBuilder::new(config).build().await?;
+
+        for set_name in &self.sets {
+            if !request
+                .config
+                .sources
+                .iter()
+                .any(|source| &source.set_name == set_name)
+            {
+                return Err(anyhow!("Set {set_name} is missing in config"));
+            }
+        }
+
+        let sources = request.config.sources.iter().filter(|source| {
+            source.map.is_none() && (self.sets.is_empty() || self.sets.contains(&source.set_name))
+        });
+
+        for source in sources {
+            let set = request.config.open_set(source);
+            let mut counters = match set.list_counters() {
+                Ok(counters) => counters,
+                Err(error) => {
+                    log::error!("Cannot read counters of {} set: {error}", source.set_name);
+                    continue;
+                }
+            };
+
+            match self.by {
+                StatsOrder::Packets => counters.sort_unstable_by_key(|counter| counter.packets),
+                StatsOrder::Bytes => counters.sort_unstable_by_key(|counter| counter.bytes),
+            }
+            counters.reverse();
+
+            let total = counters.len();
+            counters.truncate(self.top);
+            counters.retain(|counter| counter.packets > 0);
+
+            // Lists are downloaded only if elements do not carry comments
+            let origins = if counters.iter().any(|counter| counter.comment.is_none()) {
+                source
+                    .download_origins(request.fetch_context())
+                    .await
+                    .unwrap_or_else(|error| {
+                        log::error!("Cannot find origins of {} set: {error}", source.set_name);
+                        vec![]
+                    })
+            } else {
+                vec![]
+            };
+
+            println!("{} ({total} elements)", source.set_name);
+            for counter in &counters {
+                let origin = counter
+                    .comment
+                    .as_deref()
+                    .or_else(|| find_origin(counter, &origins))
+                    .unwrap_or("-");
+
+                println!(
+                    "{:>12} {:>14}  {:<43} {origin}",
+                    counter.packets,
+                    counter.bytes,
+                    counter.ip.to_string()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,48 +1,227 @@
 mod update_request;
 
-use self::update_request::UpdateRequest;
-pub(crate) use self::update_request::UpdateRequestBuilder;
+pub(crate) use self::update_request::{UpdateRequest, UpdateRequestBuilder};
 use super::{CliCommand, GlobalOptions};
-use crate::nf_helpers::NfSet;
+use crate::{
+    dnsmasq::DnsmasqSet,
+    nf_helpers::{resolve_conflicts, MapContents, MapOptions},
+    source::{GuardViolation, Source},
+};
 use anyhow::Result;
+use std::collections::HashSet;
+use tokio::sync::Mutex;
 
 #[derive(clap::Parser)]
 pub(crate) struct Command {
     #[clap(flatten)]
     global_options: GlobalOptions,
+
+    /// Replacing set is flush followed by load,
+    /// so repopulation must not see it in between
+    #[clap(skip)]
+    lock: Mutex<()>,
 }
 
 impl Command {
     pub(crate) fn new(options: GlobalOptions) -> Self {
         Self {
             global_options: options,
+            lock: Mutex::default(),
         }
     }
 
     pub(crate) async fn perform_update(&self, request: &UpdateRequest) -> Result<()> {
+        let _lock = self.lock.lock().await;
+
         let chunk_size = request.chunk_size();
         log::info!("Using chunks of {chunk_size} elements for apply operations");
 
+        let mut dnsmasq_sets = vec![];
+        let mut changed_maps = HashSet::new();
+
         for source in &request.config.sources {
-            let nfset = NfSet::with_template(
-                &source.set_name,
-                &request.config.table_name,
-                source.set_template.clone(),
-            );
+            if source.dnsmasq {
+                dnsmasq_sets.push(DnsmasqSet {
+                    set_name: &source.set_name,
+                    template: &source.set_template,
+                    domains: source.download_domains(request.fetch_context()).await?,
+                });
+                continue;
+            }
 
-            let cache = request.sources_cache();
-            let excluded = request.excluded_ips();
-            let entries = source.download_list(cache, excluded).await?;
+            let entries = match source.download_list(request.fetch_context()).await {
+                Ok(entries) => entries,
+                Err(error) if error.is::<GuardViolation>() => {
+                    log::error!("Skipping update of {} set. {error}", source.set_name);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+
+            if let Some(target) = &source.map {
+                if !entries.is_empty() {
+                    let cache = &request.fetch_context().cache;
+                    cache.set_applied(&source.set_name, entries).await;
+                    changed_maps.insert(target.name.as_str());
+                }
+                continue;
+            }
+
+            let set = request.config.open_set(source);
             if !entries.is_empty() {
-                nfset.flush()?;
-                nfset.load_entries(entries, chunk_size)?;
+                set.replace_entries(entries.clone(), chunk_size)?;
+
+                let cache = &request.fetch_context().cache;
+                cache.set_applied(&source.set_name, entries).await;
             }
+
+            if let Some(routing) = &source.routing {
+                set.apply_routing(routing)?;
+            }
+        }
+
+        self.update_maps(request, &changed_maps).await?;
+
+        match &request.config.dnsmasq {
+            Some(dnsmasq) => dnsmasq.update(
+                request.config.backend,
+                &request.config.table_name,
+                &dnsmasq_sets,
+            )?,
+            None if !dnsmasq_sets.is_empty() => {
+                log::warn!("Some sources are managed by dnsmasq, but its config path is missing");
+            }
+            None => {}
+        }
+
+        if let Some(fw4_include) = &request.config.fw4_include {
+            fw4_include.update(&request.config)?;
         }
 
         log::info!("Successfully updated all sources!");
 
         Ok(())
     }
+
+    /// Maps are refilled as a whole from cached contents of their sources
+    async fn update_maps(&self, request: &UpdateRequest, changed: &HashSet<&str>) -> Result<()> {
+        for name in changed {
+            if !request.config.maps.iter().any(|map| &map.name == name) {
+                log::warn!("Map {name} is targeted by sources, but not declared");
+            }
+        }
+
+        for map in &request.config.maps {
+            if !changed.contains(map.name.as_str()) {
+                log::info!("Map {} is up to date", map.name);
+                continue;
+            }
+
+            self.fill_map(request, map).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces map contents with cached entries of its sources.
+    /// Returns false when none of them was applied yet
+    async fn fill_map(&self, request: &UpdateRequest, map: &MapOptions) -> Result<bool> {
+        let cache = &request.fetch_context().cache;
+
+        let mut contents = vec![];
+        for source in &request.config.sources {
+            let Some(target) = source.map.as_ref().filter(|target| target.name == map.name) else {
+                continue;
+            };
+
+            if let Some(entries) = cache.get_applied(&source.set_name).await {
+                contents.push(MapContents {
+                    target,
+                    entries: entries.into_iter().map(|element| element.ip).collect(),
+                });
+            }
+        }
+
+        if contents.is_empty() {
+            return Ok(false);
+        }
+
+        let entries = resolve_conflicts(contents);
+        request
+            .config
+            .open_map(map)?
+            .replace_entries(entries, request.chunk_size())?;
+
+        Ok(true)
+    }
+
+    /// Reloads sets which disappeared or became empty since last update.
+    /// Contents are taken from cache, so nothing is downloaded
+    pub(crate) async fn repopulate(&self, request: &UpdateRequest) -> Result<()> {
+        let _lock = self.lock.lock().await;
+
+        for source in &request.config.sources {
+            if source.dnsmasq || source.map.is_some() {
+                continue;
+            }
+
+            if let Err(error) = self.repopulate_set(request, source).await {
+                log::error!("Cannot repopulate {} set: {error:?}", source.set_name);
+            }
+        }
+
+        for map in &request.config.maps {
+            if let Err(error) = self.repopulate_map(request, map).await {
+                log::error!("Cannot repopulate {} map: {error:?}", map.name);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn repopulate_map(&self, request: &UpdateRequest, map: &MapOptions) -> Result<()> {
+        match request.config.open_map(map)?.list_entries() {
+            Ok(current) if !current.is_empty() => return Ok(()),
+            Ok(_) => log::debug!("Map {} is empty", map.name),
+            Err(error) => log::debug!("Map {} is unavailable. {error}", map.name),
+        }
+
+        // Map is declared by filling, so missing one is recreated too
+        if self.fill_map(request, map).await? {
+            log::warn!("Map {} was empty or missing. Repopulated", map.name);
+        }
+
+        Ok(())
+    }
+
+    async fn repopulate_set(&self, request: &UpdateRequest, source: &Source) -> Result<()> {
+        let cache = &request.fetch_context().cache;
+        let Some(entries) = cache.get_applied(&source.set_name).await else {
+            return Ok(());
+        };
+
+        let set = request.config.open_set(source);
+        match set.list_entries() {
+            Ok(current) if !current.is_empty() => return Ok(()),
+            Ok(_) => log::warn!("Set {} is empty. Repopulating...", source.set_name),
+            Err(error) => {
+                log::warn!(
+                    "Set {} is unavailable. Repopulating... {error}",
+                    source.set_name
+                );
+            }
+        }
+
+        // nftables and ipset backends recreate missing set while loading.
+        // Netlink one cannot declare sets, so it fails until set is back
+        set.load_entries(entries, request.chunk_size())?;
+
+        if let Some(routing) = &source.routing {
+            set.apply_routing(routing)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
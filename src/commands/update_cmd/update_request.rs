@@ -1,6 +1,6 @@
 use crate::{
     config::Config,
-    source::{IPParsable, SourceProvider, SourcesCache, IP},
+    source::{DnsResolver, FetchContext, HttpClient, IPParsable, SourceProvider, SourcesCache, IP},
 };
 use anyhow::Result;
 use either::Either;
@@ -14,8 +14,7 @@ pub(crate) struct UpdateRequestBuilder {
 
 pub(crate) struct UpdateRequest {
     pub(crate) config: Config,
-    excluded_ips: Arc<HashSet<IP>>,
-    sources_cache: SourcesCache,
+    fetch_context: FetchContext,
 }
 
 impl UpdateRequest {
@@ -23,12 +22,8 @@ impl UpdateRequest {
         self.config.split_by_chunks.unwrap_or(usize::MAX)
     }
 
-    pub(crate) fn excluded_ips(&self) -> Arc<HashSet<IP>> {
-        self.excluded_ips.clone()
-    }
-
-    pub(crate) fn sources_cache(&self) -> SourcesCache {
-        self.sources_cache.clone()
+    pub(crate) fn fetch_context(&self) -> &FetchContext {
+        &self.fetch_context
     }
 }
 
@@ -53,9 +48,13 @@ impl UpdateRequestBuilder {
     }
 
     pub(crate) async fn build(mut self) -> Result<UpdateRequest> {
+        let dns_resolver = DnsResolver::new(std::mem::take(&mut self.config.dns));
+        let http_options = std::mem::take(&mut self.config.http);
+        let http_client = HttpClient::new(http_options, dns_resolver.clone())?;
+
         let excluded_ips = match self.config.excluded_ips.take() {
             Some(Either::Left(url)) => {
-                let provider = SourceProvider::new(url)?;
+                let provider = SourceProvider::new(url, &http_client)?;
 
                 // unwrap is safe here 'cause result will never equal to NotModified
                 // since there's no cache
@@ -65,10 +64,22 @@ impl UpdateRequestBuilder {
             None => HashSet::new(),
         };
 
+        let cache = match (self.sources_cache, self.config.guards_state.clone()) {
+            (Some(cache), _) => cache,
+            (None, Some(path)) => SourcesCache::with_counts_file(path),
+            (None, None) => SourcesCache::default(),
+        };
+
+        let fetch_context = FetchContext {
+            cache,
+            excluded: Arc::new(excluded_ips),
+            http_client,
+            dns_resolver,
+        };
+
         Ok(UpdateRequest {
             config: self.config,
-            sources_cache: self.sources_cache.unwrap_or_default(),
-            excluded_ips: Arc::new(excluded_ips),
+            fetch_context,
         })
     }
 }
@@ -1,10 +1,19 @@
-use crate::source::{Source, IP};
-use anyhow::Result;
+use crate::{
+    backend::{netns_path, Backend, NetnsSet, SetBackend},
+    dnsmasq::DnsmasqOptions,
+    fw4::Fw4IncludeOptions,
+    nf_helpers::{MapOptions, NfMap, NftOptions},
+    source::{DnsOptions, HttpOptions, Source, SourceUrl, IP},
+};
+use anyhow::{anyhow, Result};
 use either::Either;
 use serde::Deserialize;
 use std::collections::HashSet;
-use std::{fs::File, path::Path};
-use url::Url;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Deserialize)]
 #[serde(default)]
@@ -13,14 +22,38 @@ pub(crate) struct Config {
 
     pub(crate) table_name: String,
 
+    pub(crate) backend: Backend,
+
+    pub(crate) nft: NftOptions,
+
+    /// Network namespace name or path where sets live
+    pub(crate) netns: Option<String>,
+
     pub(crate) sources: Vec<Source>,
 
+    pub(crate) maps: Vec<MapOptions>,
+
     #[serde(with = "either::serde_untagged_optional")]
-    pub(crate) excluded_ips: Option<Either<Url, HashSet<IP>>>,
+    pub(crate) excluded_ips: Option<Either<SourceUrl, HashSet<IP>>>,
 
     pub(crate) split_by_chunks: Option<usize>,
 
     pub(crate) update_schedule: Option<String>,
+
+    /// How often daemon checks that sets are still filled
+    #[serde(with = "humantime_serde")]
+    pub(crate) repopulate_interval: Option<Duration>,
+
+    /// Keeps list sizes between runs for shrink and growth guards
+    pub(crate) guards_state: Option<PathBuf>,
+
+    pub(crate) http: HttpOptions,
+
+    pub(crate) dns: DnsOptions,
+
+    pub(crate) dnsmasq: Option<DnsmasqOptions>,
+
+    pub(crate) fw4_include: Option<Fw4IncludeOptions>,
 }
 
 impl Config {
@@ -29,6 +62,133 @@ impl Config {
         Ok(serde_yaml::from_reader(file)?)
     }
 
+    /// Checks options serde cannot. Called once logger is ready
+    pub(crate) fn validate(&mut self) -> Result<()> {
+        if let Some(netns) = self.nft.netns.take() {
+            log::warn!("nft.netns is deprecated, use top-level netns instead");
+
+            match &self.netns {
+                Some(current) if *current != netns => {
+                    return Err(anyhow!(
+                        "nft.netns ({netns}) conflicts with netns ({current})"
+                    ));
+                }
+                _ => self.netns = Some(netns),
+            }
+        }
+
+        for map in &self.maps {
+            let netns = self.map_netns(map);
+            let conflicting = self.sources.iter().find(|source| {
+                let is_target = source
+                    .map
+                    .as_ref()
+                    .is_some_and(|target| target.name == map.name);
+                is_target && source.netns.as_deref().or(self.netns.as_deref()) != netns
+            });
+
+            if let Some(source) = conflicting {
+                return Err(anyhow!(
+                    "Sources of {} map must share netns, but {} has another one",
+                    map.name,
+                    source.set_name
+                ));
+            }
+        }
+
+        if self.fw4_include.is_some() {
+            Fw4IncludeOptions::validate(self)?;
+        }
+
+        for source in &self.sources {
+            // Maps hold addresses and values only
+            if source.map.is_some() && (source.element_timeout.is_some() || source.comments) {
+                return Err(anyhow!(
+                    "Set {} fills a map, which supports neither element_timeout nor comments",
+                    source.set_name
+                ));
+            }
+
+            if source.element_timeout.is_some() && !source.set_template.has_timeouts() {
+                return Err(anyhow!(
+                    "Set {} must have timeout flag to use element_timeout",
+                    source.set_name
+                ));
+            }
+
+            let local = source
+                .urls
+                .iter()
+                .find(|url| url.verify.is_some() && matches!(url.url.scheme(), "file" | "exec"));
+            if let Some(url) = local {
+                return Err(anyhow!(
+                    "Verification of {} is supported only for remote urls",
+                    url.redacted()
+                ));
+            }
+
+            if source.set_template.counter && self.backend != Backend::Nftables {
+                log::warn!(
+                    "Counters of {} set are ignored as they are supported only by nftables backend",
+                    source.set_name
+                );
+            }
+
+            if let Some(routing) = &source.routing {
+                routing.validate(&source.set_name)?;
+            }
+
+            if source.routing.is_some() && self.backend != Backend::Nftables {
+                log::warn!(
+                    "Routing of {} set is ignored as it is supported only by nftables backend",
+                    source.set_name
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set of source in configured backend and network namespace
+    pub(crate) fn open_set(&self, source: &Source) -> Box<dyn SetBackend> {
+        let set = self.backend.open_set(
+            &source.set_name,
+            &self.table_name,
+            &source.set_template,
+            &self.nft,
+            source.routing.as_ref(),
+        );
+
+        match source.netns.as_ref().or(self.netns.as_ref()) {
+            Some(netns) => Box::new(NetnsSet::new(netns, set)),
+            None => set,
+        }
+    }
+
+    /// Maps are always managed via nft, so netlink backend falls back to it
+    pub(crate) fn open_map<'a>(&'a self, map: &'a MapOptions) -> Result<NfMap<'a>> {
+        if self.backend == Backend::Ipset {
+            return Err(anyhow!("Maps are not supported by ipset backend"));
+        }
+
+        let netns = self.map_netns(map).map(netns_path);
+        Ok(NfMap::new(map, &self.table_name, &self.nft, netns))
+    }
+
+    /// Sources of the map share network namespace, which is checked by `validate`
+    fn map_netns(&self, map: &MapOptions) -> Option<&str> {
+        self.sources
+            .iter()
+            .filter(|source| {
+                source
+                    .map
+                    .as_ref()
+                    .is_some_and(|target| target.name == map.name)
+            })
+            .find_map(|source| source.netns.as_deref())
+            .or(self.netns.as_deref())
+    }
+
     pub(crate) fn init_logger(&self) {
         simple_logger::init_with_level(self.log_level).expect("Cannot init logger");
     }
@@ -39,10 +199,82 @@ impl Default for Config {
         Self {
             log_level: log::Level::Info,
             table_name: "fw4".to_string(),
+            backend: Backend::default(),
+            nft: NftOptions::default(),
+            netns: None,
             sources: vec![],
+            maps: vec![],
             excluded_ips: None,
             split_by_chunks: None,
             update_schedule: None,
+            repopulate_interval: None,
+            guards_state: Some(PathBuf::from("/var/lib/hirkn/guards.json")),
+            http: HttpOptions::default(),
+            dns: DnsOptions::default(),
+            dnsmasq: None,
+            fw4_include: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn nft_netns_alias() {
+        let mut config: Config = serde_yaml::from_str("nft: {netns: vpn}").unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.netns.as_deref(), Some("vpn"));
+
+        let mut config: Config = serde_yaml::from_str("netns: lan\nnft: {netns: vpn}").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn element_timeout_requires_flag() {
+        let source = "sources: [{set_name: resolved, entries_limit: 0, element_timeout: 1h";
+
+        let mut config: Config = serde_yaml::from_str(&format!("{source}}}]")).unwrap();
+        assert!(config.validate().is_err());
+
+        let template = ", set_template: {flags: [timeout]}}]";
+        let mut config: Config = serde_yaml::from_str(&format!("{source}{template}")).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn verify_requires_remote_url() {
+        let verify = "verify: {sha256: 'https://example.com/list.txt.sha256'}";
+        let source = |url: &str| {
+            let url = format!("{{url: '{url}', {verify}}}");
+            format!("sources: [{{set_name: blocked, entries_limit: 0, urls: [{url}]}}]")
+        };
+
+        let mut config: Config = serde_yaml::from_str(&source("file:///tmp/list.txt")).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config: Config = serde_yaml::from_str(&source("exec:/bin/true")).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config: Config =
+            serde_yaml::from_str(&source("https://example.com/list.txt")).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn map_sources_reject_timeouts() {
+        let source = "sources: [{set_name: routed, entries_limit: 0, map: {name: routes, value: 1}";
+
+        let mut config: Config = serde_yaml::from_str(&format!("{source}}}]")).unwrap();
+        config.validate().unwrap();
+
+        let timeout = ", element_timeout: 1h, set_template: {flags: [timeout]}}]";
+        let mut config: Config = serde_yaml::from_str(&format!("{source}{timeout}")).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config: Config =
+            serde_yaml::from_str(&format!("{source}, comments: true}}]")).unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{backend::Backend, nf_helpers::keyword, source::SetTemplate, util::write_atomically};
+use anyhow::{anyhow, Result};
+use nftables::schema;
+use serde::Deserialize;
+use std::{collections::HashSet, fmt::Write, fs, path::PathBuf, process::Command};
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct DnsmasqOptions {
+    /// Config fragment path. Usually somewhere in dnsmasq `conf-dir`
+    path: PathBuf,
+    /// Program with arguments to run after config is changed
+    #[serde(default)]
+    reload_command: Vec<String>,
+}
+
+/// Domains which dnsmasq should put into the set
+pub(crate) struct DnsmasqSet<'a> {
+    pub(crate) set_name: &'a str,
+    pub(crate) template: &'a SetTemplate,
+    pub(crate) domains: HashSet<String>,
+}
+
+impl DnsmasqOptions {
+    pub(crate) fn update(
+        &self,
+        backend: Backend,
+        table_name: &str,
+        sets: &[DnsmasqSet],
+    ) -> Result<()> {
+        let config = render_config(backend, table_name, sets);
+
+        let current = fs::read_to_string(&self.path).unwrap_or_default();
+        if current == config {
+            log::info!("Dnsmasq config is up to date");
+            return Ok(());
+        }
+
+        write_atomically(&self.path, &config)?;
+        log::info!("Dnsmasq config is written to {}", self.path.display());
+
+        self.reload()
+    }
+
+    fn reload(&self) -> Result<()> {
+        let Some((program, args)) = self.reload_command.split_first() else {
+            return Ok(());
+        };
+
+        let status = Command::new(program).args(args).status()?;
+        if !status.success() {
+            return Err(anyhow!("Dnsmasq reload command failed with {status}"));
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn render_config(backend: Backend, table_name: &str, sets: &[DnsmasqSet]) -> String {
+    let mut config = String::new();
+
+    for set in sets {
+        let mut domains: Vec<_> = set.domains.iter().collect();
+        domains.sort_unstable();
+
+        let _ = writeln!(config, "# {}", set.set_name);
+
+        if backend == Backend::Ipset {
+            for domain in domains {
+                let _ = writeln!(config, "ipset=/{domain}/{}", set.set_name);
+            }
+            continue;
+        }
+
+        let family = keyword(&set.template.family);
+
+        let address_family = match set.template.set_type {
+            schema::SetTypeValue::Single(schema::SetType::Ipv4Addr) => "4#",
+            schema::SetTypeValue::Single(schema::SetType::Ipv6Addr) => "6#",
+            _ => "",
+        };
+
+        for domain in domains {
+            let _ = writeln!(
+                config,
+                "nftset=/{domain}/{address_family}{family}#{table_name}#{}",
+                set.set_name
+            );
+        }
+    }
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_config, DnsmasqSet};
+    use crate::{backend::Backend, source::SetTemplate};
+    use std::collections::HashSet;
+
+    #[test]
+    fn render_nftset_directives() {
+        let template = SetTemplate::default();
+        let sets = [DnsmasqSet {
+            set_name: "blocked",
+            template: &template,
+            domains: HashSet::from(["b.example.com".to_string(), "a.example.com".to_string()]),
+        }];
+
+        let expected = "# blocked\n\
+            nftset=/a.example.com/4#inet#fw4#blocked\n\
+            nftset=/b.example.com/4#inet#fw4#blocked\n";
+
+        assert_eq!(render_config(Backend::Nftables, "fw4", &sets), expected);
+    }
+}
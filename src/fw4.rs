@@ -0,0 +1,258 @@
+use crate::{
+    backend::Backend,
+    config::Config,
+    nf_helpers::{keyword, properties, MapValue, MapValueType, RoutingOptions},
+    source::{SetTemplate, IP},
+    util::write_atomically,
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{fmt::Write, fs, path::PathBuf};
+
+/// Set and routing chain declarations included by fw4 into its table,
+/// so they survive `fw4 reload` which recreates the table
+#[derive(Deserialize, Debug)]
+pub(crate) struct Fw4IncludeOptions {
+    /// Usually `/usr/share/nftables.d/table-post/hirkn.nft`
+    path: PathBuf,
+    /// Declare sets with elements they currently hold
+    #[serde(default)]
+    elements: bool,
+}
+
+/// Set or map when values are given
+pub(crate) struct DeclaredSet<'a> {
+    pub(crate) set_name: &'a str,
+    pub(crate) template: &'a SetTemplate,
+    pub(crate) value_type: Option<MapValueType>,
+    pub(crate) elements: Vec<(IP, Option<MapValue>)>,
+    pub(crate) routing: Option<&'a RoutingOptions>,
+}
+
+impl Fw4IncludeOptions {
+    /// fw4 includes declarations into its own `inet fw4` table
+    /// of the main network namespace only
+    pub(crate) fn validate(config: &Config) -> Result<()> {
+        if config.table_name != "fw4" {
+            return Err(anyhow!(
+                "Fw4 include requires table_name fw4, got {}",
+                config.table_name
+            ));
+        }
+
+        if config.netns.is_some() {
+            return Err(anyhow!("Fw4 include cannot declare sets in other netns"));
+        }
+
+        for source in &config.sources {
+            if source.netns.is_some() {
+                return Err(anyhow!(
+                    "Fw4 include cannot declare {} set in other netns",
+                    source.set_name
+                ));
+            }
+
+            let family = keyword(&source.set_template.family);
+            if source.map.is_none() && family != "inet" {
+                return Err(anyhow!(
+                    "Fw4 include requires inet family, but {} set has {family}",
+                    source.set_name
+                ));
+            }
+        }
+
+        for map in &config.maps {
+            let family = keyword(&map.template.family);
+            if family != "inet" {
+                return Err(anyhow!(
+                    "Fw4 include requires inet family, but {} map has {family}",
+                    map.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn update(&self, config: &Config) -> Result<()> {
+        if config.backend == Backend::Ipset {
+            log::warn!("Fw4 include is not written for ipset backend");
+            return Ok(());
+        }
+
+        let mut sets: Vec<_> = config
+            .sources
+            .iter()
+            .filter(|source| source.map.is_none())
+            .map(|source| {
+                let elements = if self.elements && !source.dnsmasq {
+                    config
+                        .open_set(source)
+                        .list_entries()
+                        .unwrap_or_else(|error| {
+                            log::warn!("Set {} is declared empty. {error}", source.set_name);
+                            vec![]
+                        })
+                } else {
+                    vec![]
+                };
+
+                DeclaredSet {
+                    set_name: &source.set_name,
+                    template: &source.set_template,
+                    value_type: None,
+                    elements: elements.into_iter().map(|ip| (ip, None)).collect(),
+                    routing: source.routing.as_ref(),
+                }
+            })
+            .collect();
+
+        for map in &config.maps {
+            let elements = if self.elements {
+                config
+                    .open_map(map)
+                    .and_then(|map| map.list_entries())
+                    .unwrap_or_else(|error| {
+                        log::warn!("Map {} is declared empty. {error}", map.name);
+                        vec![]
+                    })
+            } else {
+                vec![]
+            };
+
+            sets.push(DeclaredSet {
+                set_name: &map.name,
+                template: &map.template,
+                value_type: Some(map.value_type),
+                elements: elements
+                    .into_iter()
+                    .map(|(ip, value)| (ip, Some(value)))
+                    .collect(),
+                routing: None,
+            });
+        }
+
+        let include = render_include(&sets);
+
+        let current = fs::read_to_string(&self.path).unwrap_or_default();
+        if current == include {
+            log::info!("Fw4 include is up to date");
+            return Ok(());
+        }
+
+        write_atomically(&self.path, &include)?;
+        log::info!("Fw4 include is written to {}", self.path.display());
+
+        Ok(())
+    }
+}
+
+/// Renders sets and their routing chains in syntax of `table` block body
+pub(crate) fn render_include(sets: &[DeclaredSet]) -> String {
+    let mut include = String::new();
+
+    for set in sets {
+        let kind = if set.value_type.is_some() {
+            "map"
+        } else {
+            "set"
+        };
+        let value_type = set.value_type.as_ref().map(keyword);
+
+        let _ = writeln!(include, "{kind} {} {{", set.set_name);
+        for property in properties(set.template, value_type.as_deref()) {
+            let _ = writeln!(include, "\t{property}");
+        }
+
+        if !set.elements.is_empty() {
+            let mut elements = set.elements.clone();
+            elements.sort_unstable_by_key(|(ip, _)| ip.to_network());
+
+            let elements: Vec<_> = elements
+                .iter()
+                .map(|(ip, value)| match value {
+                    Some(value) => format!("{ip} : {value}"),
+                    None => ip.to_string(),
+                })
+                .collect();
+            let _ = writeln!(include, "\telements = {{ {} }}", elements.join(", "));
+        }
+
+        include.push_str("}\n");
+
+        if let Some(routing) = set.routing {
+            let _ = writeln!(include, "chain {} {{", routing.chain_name(set.set_name));
+            let _ = writeln!(include, "\t{};", routing.chain_properties());
+            let _ = writeln!(
+                include,
+                "\t{}",
+                routing.rule_statements(set.set_name, &set.template.set_type)
+            );
+            include.push_str("}\n");
+        }
+    }
+
+    include
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_include, DeclaredSet};
+    use crate::{
+        nf_helpers::{MapValue, MapValueType, RoutingOptions},
+        source::SetTemplate,
+    };
+
+    #[test]
+    fn render_set_declarations() {
+        let template = SetTemplate::default();
+        let routing: RoutingOptions = serde_yaml::from_str("mark: 16").unwrap();
+        let sets = [
+            DeclaredSet {
+                set_name: "blocked",
+                template: &template,
+                value_type: None,
+                elements: vec![
+                    ("10.0.0.0/8".parse().unwrap(), None),
+                    ("1.1.1.1".parse().unwrap(), None),
+                ],
+                routing: Some(&routing),
+            },
+            DeclaredSet {
+                set_name: "resolved",
+                template: &template,
+                value_type: None,
+                elements: vec![],
+                routing: None,
+            },
+            DeclaredSet {
+                set_name: "routes",
+                template: &template,
+                value_type: Some(MapValueType::Mark),
+                elements: vec![("10.0.0.0/8".parse().unwrap(), Some(MapValue::Mark(1)))],
+                routing: None,
+            },
+        ];
+
+        let expected = "set blocked {\n\
+            \ttype ipv4_addr\n\
+            \tflags interval\n\
+            \telements = { 1.1.1.1, 10.0.0.0/8 }\n\
+            }\n\
+            chain hirkn_blocked {\n\
+            \ttype filter hook prerouting priority -150;\n\
+            \tip daddr @blocked meta mark set 0x10\n\
+            }\n\
+            set resolved {\n\
+            \ttype ipv4_addr\n\
+            \tflags interval\n\
+            }\n\
+            map routes {\n\
+            \ttype ipv4_addr : mark\n\
+            \tflags interval\n\
+            \telements = { 10.0.0.0/8 : 0x1 }\n\
+            }\n";
+
+        assert_eq!(render_include(&sets), expected);
+    }
+}
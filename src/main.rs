@@ -4,10 +4,14 @@
 #[macro_use]
 extern crate async_trait;
 
+mod backend;
 mod commands;
 mod config;
+mod dnsmasq;
+mod fw4;
 mod nf_helpers;
 mod source;
+mod util;
 
 use self::commands::{CliCommand, Command};
 use anyhow::Result;
@@ -27,5 +31,8 @@ async fn main() -> Result<()> {
         Command::Update(command) => command.run().await,
         Command::RunDaemon(command) => command.run().await,
         Command::Flush(command) => command.run().await,
+        Command::Load(command) => command.run().await,
+        Command::Export(command) => command.run().await,
+        Command::Stats(command) => command.run().await,
     }
 }
@@ -1,3 +1,62 @@
+mod nfmap;
 mod nfset;
+mod nft;
+mod routing;
 
-pub(crate) use self::nfset::NfSet;
+pub(crate) use self::{
+    nfmap::{resolve_conflicts, MapContents, MapOptions, MapTarget, MapValue, MapValueType, NfMap},
+    nfset::NfSet,
+    nft::NftOptions,
+    routing::RoutingOptions,
+};
+use crate::source::{SetSize, SetTemplate};
+use nftables::schema;
+use serde::Serialize;
+
+/// Returns nft keyword of family, set type, flag and so on
+/// as they are serialized the same way in JSON
+pub(crate) fn keyword(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(keyword)) => keyword,
+        _ => String::new(),
+    }
+}
+
+/// Declaration statements of set like `type ipv4_addr` or `flags interval`.
+/// Maps also pass type of their values
+pub(crate) fn properties(template: &SetTemplate, value_type: Option<&str>) -> Vec<String> {
+    let mut key_type = match &template.set_type {
+        schema::SetTypeValue::Single(set_type) => keyword(set_type),
+        schema::SetTypeValue::Concatenated(types) => {
+            types.iter().map(keyword).collect::<Vec<_>>().join(" . ")
+        }
+    };
+    if let Some(value_type) = value_type {
+        key_type = format!("{key_type} : {value_type}");
+    }
+
+    let mut properties = vec![format!("type {key_type}")];
+
+    if let Some(flags) = &template.flags {
+        let mut flags: Vec<_> = flags.iter().map(keyword).collect();
+        flags.sort_unstable();
+        properties.push(format!("flags {}", flags.join(", ")));
+    }
+    if let Some(timeout) = template.timeout {
+        properties.push(format!("timeout {timeout}s"));
+    }
+    if let Some(gc_interval) = template.gc_interval {
+        properties.push(format!("gc-interval {gc_interval}s"));
+    }
+    if let Some(policy) = &template.policy {
+        properties.push(format!("policy {}", keyword(policy)));
+    }
+    if let Some(SetSize::Fixed(size)) = template.size {
+        properties.push(format!("size {size}"));
+    }
+    if template.counter {
+        properties.push("counter".to_string());
+    }
+
+    properties
+}
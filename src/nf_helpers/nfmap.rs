@@ -0,0 +1,449 @@
+use super::{keyword, nfset::parse_element, properties, routing::RoutingVerdict, NftOptions};
+use crate::{
+    backend::run_in_netns,
+    source::{SetTemplate, IP},
+};
+use anyhow::{anyhow, Result};
+use ipnet::{IpNet, Ipv4Subnets, Ipv6Subnets};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Write},
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MapValueType {
+    Mark,
+    Verdict,
+}
+
+/// Map filled by several sources with different values
+#[derive(Deserialize, Debug)]
+pub(crate) struct MapOptions {
+    pub(crate) name: String,
+    /// Key type and flags of the map
+    #[serde(default)]
+    pub(crate) template: SetTemplate,
+    pub(crate) value_type: MapValueType,
+}
+
+/// Makes source fill the map instead of its own set.
+/// `set_name` of such source only identifies it
+#[derive(Deserialize, Debug)]
+pub(crate) struct MapTarget {
+    pub(crate) name: String,
+    pub(crate) value: MapValue,
+    /// Entries of sources with higher priority win conflicts
+    #[serde(default)]
+    pub(crate) priority: i32,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum MapValue {
+    Mark(u32),
+    Verdict(RoutingVerdict),
+}
+
+impl MapValue {
+    fn value_type(&self) -> MapValueType {
+        match self {
+            Self::Mark(_) => MapValueType::Mark,
+            Self::Verdict(_) => MapValueType::Verdict,
+        }
+    }
+
+    /// Converts value back from JSON listing of nft
+    fn from_json(value: &Value) -> Option<Self> {
+        if let Some(mark) = value.as_u64() {
+            return Some(Self::Mark(u32::try_from(mark).ok()?));
+        }
+
+        let (verdict, argument) = value.as_object()?.iter().next()?;
+        let target = || argument["target"].as_str().map(ToString::to_string);
+
+        let verdict = match verdict.as_str() {
+            "accept" => RoutingVerdict::Accept,
+            "drop" => RoutingVerdict::Drop,
+            "return" => RoutingVerdict::Return,
+            "jump" => RoutingVerdict::Jump(target()?),
+            "goto" => RoutingVerdict::Goto(target()?),
+            _ => return None,
+        };
+
+        Some(Self::Verdict(verdict))
+    }
+}
+
+impl Display for MapValue {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mark(mark) => write!(formatter, "{mark:#x}"),
+            Self::Verdict(verdict) => verdict.fmt(formatter),
+        }
+    }
+}
+
+/// Entries of single source targeting the map
+pub(crate) struct MapContents<'a> {
+    pub(crate) target: &'a MapTarget,
+    pub(crate) entries: Vec<IP>,
+}
+
+pub(crate) struct NfMap<'a> {
+    options: &'a MapOptions,
+    table_name: &'a str,
+    nft: &'a NftOptions,
+    netns: Option<PathBuf>,
+}
+
+impl<'a> NfMap<'a> {
+    pub(crate) fn new(
+        options: &'a MapOptions,
+        table_name: &'a str,
+        nft: &'a NftOptions,
+        netns: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            options,
+            table_name,
+            nft,
+            netns,
+        }
+    }
+
+    fn target(&self) -> String {
+        let family = keyword(&self.options.template.family);
+        format!("{family} {} {}", self.table_name, self.options.name)
+    }
+
+    fn run<T: Send>(&self, operation: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+        match &self.netns {
+            Some(netns) => run_in_netns(netns, operation),
+            None => operation(),
+        }
+    }
+
+    fn run_script(&self, script: &str) -> Result<()> {
+        self.run(|| self.nft.run_script(script))
+    }
+
+    /// Elements with their values. Ranges are split into subnets
+    pub(crate) fn list_entries(&self) -> Result<Vec<(IP, MapValue)>> {
+        let output = self.run(|| {
+            let family = keyword(&self.options.template.family);
+            let output = self
+                .nft
+                .command()
+                .args([
+                    "-j",
+                    "list",
+                    "map",
+                    &family,
+                    self.table_name,
+                    &self.options.name,
+                ])
+                .output()?;
+            Ok(output)
+        })?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Cannot list {} map: {}",
+                self.options.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let ruleset: Value = serde_json::from_slice(&output.stdout)?;
+        Ok(parse_map_elements(&ruleset))
+    }
+
+    pub(crate) fn flush(&self) -> Result<()> {
+        self.run_script(&format!("flush map {}\n", self.target()))
+    }
+
+    /// Declares the map and replaces its contents in single transaction
+    pub(crate) fn replace_entries(
+        &self,
+        entries: Vec<(IpNet, MapValue)>,
+        chunk_size: usize,
+    ) -> Result<()> {
+        let value_type = self.options.value_type;
+        if let Some((_, value)) = entries
+            .iter()
+            .find(|(_, value)| value.value_type() != value_type)
+        {
+            return Err(anyhow!(
+                "Value {value} does not match type of {} map",
+                self.options.name
+            ));
+        }
+
+        log::info!(
+            "Resolved {} elements for {} map. Applying...",
+            entries.len(),
+            self.options.name
+        );
+
+        let target = self.target();
+        let properties = properties(&self.options.template, Some(&keyword(&value_type)));
+
+        let mut script = format!("add map {target} {{ {}; }}\n", properties.join("; "));
+        let _ = writeln!(script, "flush map {target}");
+
+        for chunk in &entries.into_iter().chunks(chunk_size) {
+            let elements = chunk
+                .map(|(network, value)| format!("{network} : {value}"))
+                .join(", ");
+            let _ = writeln!(script, "add element {target} {{ {elements} }}");
+        }
+
+        self.run_script(&script)
+    }
+}
+
+fn parse_map_elements(ruleset: &Value) -> Vec<(IP, MapValue)> {
+    ruleset["nftables"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|object| object["map"]["elem"].as_array())
+        .flatten()
+        .filter_map(|element| {
+            let value = MapValue::from_json(element.get(1)?)?;
+            let keys = parse_element(element.get(0)?);
+            Some(keys.into_iter().map(move |key| (key, value.clone())))
+        })
+        .flatten()
+        .collect()
+}
+
+/// Merges entries of all sources. Parts of networks overlapping ones of source
+/// with higher priority are cut out, as interval maps cannot hold overlaps
+pub(crate) fn resolve_conflicts(mut contents: Vec<MapContents>) -> Vec<(IpNet, MapValue)> {
+    contents.sort_by_key(|contents| std::cmp::Reverse(contents.target.priority));
+
+    // (is IPv6, first address) => last address
+    let mut taken: BTreeMap<(bool, u128), u128> = BTreeMap::new();
+    let mut resolved = vec![];
+    let mut trimmed = 0;
+
+    for contents in contents {
+        let networks: Vec<_> = contents.entries.iter().map(IP::to_network).collect();
+
+        for network in IpNet::aggregate(&networks) {
+            let (is_ipv6, first, last) = bounds(&network);
+
+            let free = free_ranges(&taken, is_ipv6, first, last);
+            if free != [(first, last)] {
+                trimmed += 1;
+            }
+
+            for (start, end) in free {
+                taken.insert((is_ipv6, start), end);
+                resolved.extend(
+                    range_subnets(is_ipv6, start, end)
+                        .map(|subnet| (subnet, contents.target.value.clone())),
+                );
+            }
+        }
+    }
+
+    if trimmed > 0 {
+        log::warn!("Trimmed {trimmed} map elements conflicting with higher priority sources");
+    }
+
+    resolved
+}
+
+/// Parts of range not covered by taken ones. Taken ranges never overlap
+fn free_ranges(
+    taken: &BTreeMap<(bool, u128), u128>,
+    is_ipv6: bool,
+    first: u128,
+    last: u128,
+) -> Vec<(u128, u128)> {
+    let mut free = vec![];
+
+    // Range which starts before this one may still cover its beginning
+    let mut cursor = match taken.range(..(is_ipv6, first)).next_back() {
+        Some((&(family, _), &end)) if family == is_ipv6 && end >= first => end.checked_add(1),
+        _ => Some(first),
+    };
+
+    for (&(_, start), &end) in taken.range((is_ipv6, first)..=(is_ipv6, last)) {
+        let Some(current) = cursor else {
+            break;
+        };
+
+        if start > current {
+            free.push((current, start - 1));
+        }
+        cursor = end.checked_add(1);
+    }
+
+    if let Some(current) = cursor.filter(|&current| current <= last) {
+        free.push((current, last));
+    }
+
+    free
+}
+
+fn range_subnets(is_ipv6: bool, start: u128, end: u128) -> Box<dyn Iterator<Item = IpNet>> {
+    if is_ipv6 {
+        let subnets = Ipv6Subnets::new(Ipv6Addr::from(start), Ipv6Addr::from(end), 0);
+        return Box::new(subnets.map(IpNet::V6));
+    }
+
+    // IPv4 bounds always fit
+    let start = u32::try_from(start).unwrap_or_default();
+    let end = u32::try_from(end).unwrap_or_default();
+    let subnets = Ipv4Subnets::new(Ipv4Addr::from(start), Ipv4Addr::from(end), 0);
+    Box::new(subnets.map(IpNet::V4))
+}
+
+fn bounds(network: &IpNet) -> (bool, u128, u128) {
+    match network {
+        IpNet::V4(network) => (
+            false,
+            u32::from(network.network()).into(),
+            u32::from(network.broadcast()).into(),
+        ),
+        IpNet::V6(network) => (true, network.network().into(), network.broadcast().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_map_elements, resolve_conflicts, MapContents, MapTarget, MapValue};
+    use crate::nf_helpers::routing::RoutingVerdict;
+    use serde_json::json;
+
+    #[test]
+    fn higher_priority_wins() {
+        let vpn = MapTarget {
+            name: "routes".to_string(),
+            value: MapValue::Mark(1),
+            priority: 10,
+        };
+        let fallback = MapTarget {
+            name: "routes".to_string(),
+            value: MapValue::Mark(2),
+            priority: 0,
+        };
+
+        let contents = vec![
+            MapContents {
+                target: &fallback,
+                entries: vec![
+                    "10.0.0.0/8".parse().unwrap(),
+                    "192.168.0.1".parse().unwrap(),
+                ],
+            },
+            MapContents {
+                target: &vpn,
+                entries: vec!["10.1.0.0/16".parse().unwrap()],
+            },
+        ];
+
+        let resolved: Vec<_> = resolve_conflicts(contents)
+            .into_iter()
+            .map(|(network, value)| format!("{network} : {value}"))
+            .collect();
+
+        assert_eq!(
+            resolved,
+            [
+                "10.1.0.0/16 : 0x1",
+                "10.0.0.0/16 : 0x2",
+                "10.2.0.0/15 : 0x2",
+                "10.4.0.0/14 : 0x2",
+                "10.8.0.0/13 : 0x2",
+                "10.16.0.0/12 : 0x2",
+                "10.32.0.0/11 : 0x2",
+                "10.64.0.0/10 : 0x2",
+                "10.128.0.0/9 : 0x2",
+                "192.168.0.1/32 : 0x2",
+            ]
+        );
+    }
+
+    #[test]
+    fn trim_partially_overlapping() {
+        let vpn = MapTarget {
+            name: "routes".to_string(),
+            value: MapValue::Mark(1),
+            priority: 10,
+        };
+        let fallback = MapTarget {
+            name: "routes".to_string(),
+            value: MapValue::Mark(2),
+            priority: 0,
+        };
+
+        let contents = vec![
+            MapContents {
+                target: &vpn,
+                entries: vec!["10.0.0.2/31".parse().unwrap(), "10.0.0.8".parse().unwrap()],
+            },
+            MapContents {
+                target: &fallback,
+                entries: vec!["10.0.0.0/28".parse().unwrap(), "10.0.0.4".parse().unwrap()],
+            },
+        ];
+
+        let resolved: Vec<_> = resolve_conflicts(contents)
+            .into_iter()
+            .map(|(network, value)| format!("{network} : {value}"))
+            .collect();
+
+        assert_eq!(
+            resolved,
+            [
+                "10.0.0.2/31 : 0x1",
+                "10.0.0.8/32 : 0x1",
+                "10.0.0.0/31 : 0x2",
+                "10.0.0.4/30 : 0x2",
+                "10.0.0.9/32 : 0x2",
+                "10.0.0.10/31 : 0x2",
+                "10.0.0.12/30 : 0x2",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_json_map() {
+        let ruleset = json!({"nftables": [
+            {"metainfo": {"json_schema_version": 1}},
+            {"map": {"name": "routes", "map": "verdict", "elem": [
+                [{"prefix": {"addr": "10.0.0.0", "len": 8}}, {"jump": {"target": "vpn"}}],
+                ["1.1.1.1", {"accept": null}],
+            ]}},
+        ]});
+
+        let entries: Vec<_> = parse_map_elements(&ruleset)
+            .into_iter()
+            .map(|(ip, value)| (ip.to_string(), value))
+            .collect();
+
+        assert_eq!(
+            entries,
+            [
+                (
+                    "10.0.0.0/8".to_string(),
+                    MapValue::Verdict(RoutingVerdict::Jump("vpn".to_string()))
+                ),
+                (
+                    "1.1.1.1".to_string(),
+                    MapValue::Verdict(RoutingVerdict::Accept)
+                ),
+            ]
+        );
+    }
+}
@@ -1,15 +1,34 @@
-use crate::source::SetTemplate;
-use anyhow::Result;
+use super::{keyword, properties, NftOptions, RoutingOptions};
+use crate::{
+    backend::{ElementCounter, SetBackend},
+    source::{SetElement, SetSize, SetTemplate, IP},
+};
+use anyhow::{anyhow, Result};
+use ipnet::{IpNet, Ipv4Subnets, Ipv6Subnets};
 use itertools::Itertools;
 use nftables::{
     batch::Batch,
     expr::Expression,
-    helper::apply_ruleset,
-    schema::{self, FlushObject, NfCmd, NfListObject},
+    schema::{self, FlushObject, NfCmd, NfListObject, SetFlag},
 };
+use serde_json::Value;
+use std::{fmt::Write, net::IpAddr};
+
+/// Auto sized sets never get smaller than this
+const MIN_AUTO_SIZE: usize = 1024;
+
+enum LiveSize {
+    Missing,
+    Unlimited,
+    Limited { size: u32, elements: Vec<IP> },
+}
 
 pub(crate) struct NfSet {
     inner: schema::Set,
+    nft: NftOptions,
+    template: SetTemplate,
+    /// Own chain referencing the set, detached while set is recreated
+    routing: Option<RoutingOptions>,
 }
 
 impl NfSet {
@@ -17,61 +36,404 @@ impl NfSet {
         name: impl Into<String>,
         table_name: impl Into<String>,
         template: SetTemplate,
+        nft: NftOptions,
+        routing: Option<RoutingOptions>,
     ) -> Self {
+        let size = match template.size {
+            Some(SetSize::Fixed(size)) => Some(size),
+            _ => None,
+        };
+
         let inner = schema::Set {
             family: template.family,
             table: table_name.into(),
             name: name.into(),
             handle: None,
-            set_type: template.set_type,
+            set_type: template.set_type.clone(),
             policy: template.policy,
-            flags: template.flags,
+            flags: template.flags.clone(),
             elem: None,
             timeout: template.timeout,
             gc_interval: template.gc_interval,
-            size: None,
+            size,
         };
 
-        Self { inner }
+        Self {
+            inner,
+            nft,
+            template,
+            routing,
+        }
+    }
+
+    /// Set declaration fitting `count` more elements. Auto sized set
+    /// which is too small is recreated, so elements it held are returned
+    /// to be loaded again
+    fn declare(&self, count: usize) -> Result<(schema::Set, Vec<IP>)> {
+        let mut set = self.inner.clone();
+        if self.template.size != Some(SetSize::Auto) {
+            return Ok((set, vec![]));
+        }
+
+        let is_interval = set
+            .flags
+            .as_ref()
+            .is_some_and(|flags| flags.contains(&SetFlag::Interval));
+
+        match self.live_size()? {
+            LiveSize::Missing => set.size = Some(auto_size(count, is_interval)),
+            LiveSize::Unlimited => {}
+            LiveSize::Limited { size, elements } => {
+                let required = auto_size(elements.len() + count, is_interval);
+                if size >= required {
+                    set.size = Some(size);
+                    return Ok((set, vec![]));
+                }
+
+                log::warn!(
+                    "Set {} of size {size} is too small for {} elements. \
+                    Recreating with size {required}...",
+                    set.name,
+                    elements.len() + count
+                );
+
+                self.nft
+                    .run_script(&self.recreate_script(required))
+                    .map_err(|error| {
+                        anyhow!(
+                            "Cannot recreate {} set. It may be referenced by rules. {error}",
+                            set.name
+                        )
+                    })?;
+
+                set.size = Some(required);
+                return Ok((set, elements));
+            }
+        }
+
+        Ok((set, vec![]))
     }
 
-    pub(crate) fn flush(&self) -> Result<()> {
+    /// Deletes the set and adds it with new size in single transaction.
+    /// Own routing rule is detached meanwhile, as it references the set
+    fn recreate_script(&self, size: u32) -> String {
+        let target = format!(
+            "{} {} {}",
+            keyword(&self.inner.family),
+            self.inner.table,
+            self.inner.name
+        );
+
+        let mut template = self.template.clone();
+        template.size = Some(SetSize::Fixed(size));
+
+        let mut script = String::new();
+        if let Some(routing) = &self.routing {
+            script.push_str(&routing.detach_script(&self.inner));
+        }
+
+        let _ = writeln!(script, "delete set {target}");
+        let _ = writeln!(
+            script,
+            "add set {target} {{ {}; }}",
+            properties(&template, None).join("; ")
+        );
+
+        if let Some(routing) = &self.routing {
+            script.push_str(&routing.rule_script(&self.inner));
+        }
+
+        script
+    }
+
+    /// Elements are listed only for limited sets, which are usually flushed before loading
+    fn live_size(&self) -> Result<LiveSize> {
+        let output = self
+            .nft
+            .command()
+            .args(["-t", "-j", "list", "set"])
+            .arg(keyword(&self.inner.family))
+            .arg(&self.inner.table)
+            .arg(&self.inner.name)
+            .output();
+
+        let ruleset: Option<Value> = output
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| serde_json::from_slice(&output.stdout).ok());
+
+        let set = ruleset.as_ref().and_then(|ruleset| {
+            ruleset["nftables"]
+                .as_array()?
+                .iter()
+                .find_map(|object| object.get("set"))
+        });
+
+        let live_size = match set.map(|set| set["size"].as_u64()) {
+            None => LiveSize::Missing,
+            Some(None) => LiveSize::Unlimited,
+            Some(Some(size)) => LiveSize::Limited {
+                size: u32::try_from(size).unwrap_or(u32::MAX),
+                elements: self.list_entries()?,
+            },
+        };
+
+        Ok(live_size)
+    }
+
+    /// Elements in JSON as they are listed by nft
+    fn list_elements(&self) -> Result<Vec<Value>> {
+        let output = self
+            .nft
+            .command()
+            .args(["-j", "list", "set"])
+            .arg(keyword(&self.inner.family))
+            .arg(&self.inner.table)
+            .arg(&self.inner.name)
+            .output()?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Cannot list {} set: {}",
+                self.inner.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let mut ruleset: Value = serde_json::from_slice(&output.stdout)?;
+
+        let elements = ruleset["nftables"]
+            .as_array_mut()
+            .into_iter()
+            .flatten()
+            .filter_map(|object| object["set"]["elem"].as_array_mut())
+            .flat_map(std::mem::take)
+            .collect();
+
+        Ok(elements)
+    }
+}
+
+impl SetBackend for NfSet {
+    fn flush(&self) -> Result<()> {
         let mut batch = Batch::new();
 
         let object = FlushObject::Set(self.inner.clone());
         batch.add_cmd(NfCmd::Flush(object));
 
         let nftables = batch.to_nftables();
-        apply_ruleset(&nftables, None, None)?;
+        self.nft.apply(&nftables)?;
 
         Ok(())
     }
 
-    pub(crate) fn load_entries(&self, entries: Vec<Expression>, chunk_size: usize) -> Result<()> {
+    fn load_entries(&self, entries: Vec<SetElement>, chunk_size: usize) -> Result<()> {
         log::info!(
             "Downloaded {} elements for {} set. Applying...",
             entries.len(),
             self.inner.name
         );
 
+        let (set, reloaded) = self.declare(entries.len())?;
+        let mut entries = entries;
+        entries.extend(reloaded.into_iter().map(SetElement::from));
+
+        // JSON schema cannot declare set counters, so such sets are declared by script
+        if self.template.counter {
+            let mut template = self.template.clone();
+            template.size = set.size.map(SetSize::Fixed);
+
+            self.nft.run_script(&format!(
+                "add set {} {} {} {{ {}; }}\n",
+                keyword(&set.family),
+                set.table,
+                set.name,
+                properties(&template, None).join("; ")
+            ))?;
+        }
+
         let chunked: Vec<Vec<_>> = entries
             .into_iter()
+            .map(Expression::from)
             .chunks(chunk_size)
             .into_iter()
             .map(Iterator::collect)
             .collect();
 
         for chunk in chunked {
-            let mut set = self.inner.clone();
+            let mut set = set.clone();
             set.elem = Some(chunk);
 
             let mut batch = Batch::new();
             batch.add(NfListObject::Set(set));
 
             let nftables = batch.to_nftables();
-            apply_ruleset(&nftables, None, None)?;
+            self.nft.apply(&nftables)?;
         }
 
         Ok(())
     }
+
+    fn list_entries(&self) -> Result<Vec<IP>> {
+        let elements = self.list_elements()?;
+        Ok(elements.iter().flat_map(parse_element).collect())
+    }
+
+    fn list_counters(&self) -> Result<Vec<ElementCounter>> {
+        let elements = self.list_elements()?;
+        Ok(elements.iter().flat_map(parse_counter).collect())
+    }
+
+    fn apply_routing(&self, routing: &RoutingOptions) -> Result<()> {
+        self.nft.apply(&routing.apply_batch(&self.inner))
+    }
+
+    fn remove_routing(&self, routing: &RoutingOptions) -> Result<()> {
+        self.nft.apply(&routing.remove_batch(&self.inner))
+    }
+}
+
+/// Power of two with a quarter of headroom. Intervals take
+/// two kernel elements, one for each of their ends
+fn auto_size(count: usize, is_interval: bool) -> u32 {
+    let count = if is_interval { count * 2 } else { count };
+    let size = (count + count / 4).max(MIN_AUTO_SIZE).next_power_of_two();
+
+    u32::try_from(size).unwrap_or(u32::MAX)
+}
+
+/// Ranges split into subnets share counter of the range
+fn parse_counter(element: &Value) -> Vec<ElementCounter> {
+    let elem = &element["elem"];
+    let packets = elem["counter"]["packets"].as_u64().unwrap_or_default();
+    let bytes = elem["counter"]["bytes"].as_u64().unwrap_or_default();
+    let comment = elem["comment"].as_str().map(ToString::to_string);
+
+    parse_element(element)
+        .into_iter()
+        .map(|ip| ElementCounter {
+            ip,
+            packets,
+            bytes,
+            comment: comment.clone(),
+        })
+        .collect()
+}
+
+/// Converts JSON element back. Ranges which are not prefixes are split into subnets
+pub(super) fn parse_element(element: &Value) -> Vec<IP> {
+    if let Some(address) = element.as_str() {
+        return address.parse().into_iter().collect();
+    }
+
+    if let Some(elem) = element.get("elem") {
+        return parse_element(&elem["val"]);
+    }
+
+    if let Some(prefix) = element.get("prefix") {
+        let network = prefix["addr"]
+            .as_str()
+            .and_then(|address| address.parse::<IpAddr>().ok())
+            .zip(prefix["len"].as_u64())
+            .and_then(|(address, len)| IpNet::new(address, u8::try_from(len).ok()?).ok());
+
+        return network.map(IP::Network).into_iter().collect();
+    }
+
+    let range = element["range"]
+        .as_array()
+        .and_then(|range| range.first().zip(range.get(1)))
+        .and_then(|(start, end)| start.as_str().zip(end.as_str()))
+        .and_then(|(start, end)| start.parse::<IpAddr>().ok().zip(end.parse().ok()));
+
+    match range {
+        Some((IpAddr::V4(start), IpAddr::V4(end))) => Ipv4Subnets::new(start, end, 0)
+            .map(|subnet| IP::Network(subnet.into()))
+            .collect(),
+        Some((IpAddr::V6(start), IpAddr::V6(end))) => Ipv6Subnets::new(start, end, 0)
+            .map(|subnet| IP::Network(subnet.into()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{auto_size, parse_counter, parse_element, NfSet};
+    use crate::{nf_helpers::NftOptions, source::SetTemplate};
+    use serde_json::json;
+
+    #[test]
+    fn parse_json_elements() {
+        let elements = [
+            json!("1.1.1.1"),
+            json!({"prefix": {"addr": "10.0.0.0", "len": 8}}),
+            json!({"elem": {"val": "::1", "timeout": 60}}),
+            json!({"range": ["192.168.0.0", "192.168.2.255"]}),
+        ];
+
+        let parsed: Vec<String> = elements
+            .iter()
+            .flat_map(parse_element)
+            .map(|ip| ip.to_string())
+            .collect();
+
+        assert_eq!(
+            parsed,
+            [
+                "1.1.1.1",
+                "10.0.0.0/8",
+                "::1",
+                "192.168.0.0/23",
+                "192.168.2.0/24"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_json_counters() {
+        let element = json!({"elem": {
+            "val": {"prefix": {"addr": "10.0.0.0", "len": 8}},
+            "counter": {"packets": 5, "bytes": 420},
+            "comment": "https://example.com/list.txt",
+        }});
+
+        let counters = parse_counter(&element);
+        assert_eq!(counters.len(), 1);
+        assert_eq!(counters[0].ip.to_string(), "10.0.0.0/8");
+        assert_eq!((counters[0].packets, counters[0].bytes), (5, 420));
+        assert_eq!(
+            counters[0].comment.as_deref(),
+            Some("https://example.com/list.txt")
+        );
+    }
+
+    #[test]
+    fn auto_size_has_headroom() {
+        assert_eq!(auto_size(0, false), 1024);
+        assert_eq!(auto_size(1000, false), 2048);
+        assert_eq!(auto_size(1000, true), 4096);
+        assert_eq!(auto_size(100_000, true), 262_144);
+    }
+
+    #[test]
+    fn recreate_with_routing() {
+        let routing = serde_yaml::from_str("mark: 16").unwrap();
+        let set = NfSet::with_template(
+            "blocked",
+            "fw4",
+            SetTemplate::default(),
+            NftOptions::default(),
+            Some(routing),
+        );
+
+        assert_eq!(
+            set.recreate_script(4096),
+            "add chain inet fw4 hirkn_blocked { type filter hook prerouting priority -150; }\n\
+            flush chain inet fw4 hirkn_blocked\n\
+            delete set inet fw4 blocked\n\
+            add set inet fw4 blocked { type ipv4_addr; flags interval; size 4096; }\n\
+            add rule inet fw4 hirkn_blocked ip daddr @blocked meta mark set 0x10\n"
+        );
+    }
 }
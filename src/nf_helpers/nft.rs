@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use nftables::{helper::apply_ruleset, schema::Nftables};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub(crate) struct NftOptions {
+    /// Path to nft or its wrapper
+    program: Option<String>,
+    /// Passed before arguments added by hirkn
+    args: Vec<String>,
+    /// Deprecated alias of top-level `netns`
+    pub(crate) netns: Option<String>,
+}
+
+impl NftOptions {
+    fn program(&self) -> &str {
+        self.program.as_deref().unwrap_or("nft")
+    }
+
+    pub(crate) fn command(&self) -> Command {
+        let mut command = Command::new(self.program());
+        command.args(&self.args);
+        command
+    }
+
+    pub(crate) fn apply(&self, nftables: &Nftables) -> Result<()> {
+        let args = self.args.iter().map(String::as_str).collect();
+        apply_ruleset(nftables, Some(self.program()), Some(args))?;
+
+        Ok(())
+    }
+
+    /// Applies script in nft syntax as single transaction
+    pub(crate) fn run_script(&self, script: &str) -> Result<()> {
+        let mut child = self
+            .command()
+            .args(["-f", "-"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes())?;
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "nft failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
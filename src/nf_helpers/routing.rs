@@ -0,0 +1,287 @@
+use super::keyword;
+use anyhow::{anyhow, Result};
+use nftables::{
+    batch::Batch,
+    expr::{Expression, Meta, MetaKey, NamedExpression, Payload, PayloadField},
+    schema::{self, NfCmd, NfListObject, Nftables},
+    stmt::{JumpTarget, Mangle, Match, Operator, Statement},
+    types::{NfChainType, NfHook},
+};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter, Write};
+
+/// Priority of `mangle` chains
+const MANGLE_PRIORITY: i32 = -150;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RoutingVerdict {
+    Accept,
+    Drop,
+    Return,
+    Jump(String),
+    Goto(String),
+}
+
+impl Display for RoutingVerdict {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Accept => formatter.write_str("accept"),
+            Self::Drop => formatter.write_str("drop"),
+            Self::Return => formatter.write_str("return"),
+            Self::Jump(chain) => write!(formatter, "jump {chain}"),
+            Self::Goto(chain) => write!(formatter, "goto {chain}"),
+        }
+    }
+}
+
+/// Chain owned by hirkn which marks or redirects traffic matching the set
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub(crate) struct RoutingOptions {
+    /// Defaults to `hirkn_<set name>`
+    chain: Option<String>,
+    hook: NfHook,
+    #[serde(rename = "type")]
+    chain_type: NfChainType,
+    priority: i32,
+    /// Match source address instead of destination one
+    source_address: bool,
+    /// Value of `meta mark` for matched packets
+    mark: Option<u32>,
+    verdict: Option<RoutingVerdict>,
+}
+
+impl Default for RoutingOptions {
+    fn default() -> Self {
+        Self {
+            chain: None,
+            hook: NfHook::Prerouting,
+            chain_type: NfChainType::Filter,
+            priority: MANGLE_PRIORITY,
+            source_address: false,
+            mark: None,
+            verdict: None,
+        }
+    }
+}
+
+impl RoutingOptions {
+    /// Rule without mark and verdict would only count packets
+    pub(crate) fn validate(&self, set_name: &str) -> Result<()> {
+        if self.mark.is_none() && self.verdict.is_none() {
+            return Err(anyhow!("Routing of {set_name} set needs mark or verdict"));
+        }
+
+        Ok(())
+    }
+
+    /// Creates the chain or replaces its rules
+    pub(crate) fn apply_batch(&self, set: &schema::Set) -> Nftables {
+        let chain = self.chain(set);
+
+        let mut batch = Batch::new();
+        batch.add(NfListObject::Chain(chain.clone()));
+        batch.add_cmd(NfCmd::Flush(schema::FlushObject::Chain(chain)));
+        batch.add(NfListObject::Rule(self.rule(set)));
+
+        batch.to_nftables()
+    }
+
+    /// Deletes the chain. It is added first, so missing chain is not an error
+    pub(crate) fn remove_batch(&self, set: &schema::Set) -> Nftables {
+        let chain = self.chain(set);
+
+        let mut batch = Batch::new();
+        batch.add(NfListObject::Chain(chain.clone()));
+        batch.add_cmd(NfCmd::Flush(schema::FlushObject::Chain(chain.clone())));
+        batch.delete(NfListObject::Chain(chain));
+
+        batch.to_nftables()
+    }
+
+    /// Adds the chain and flushes its rules in nft syntax, so it can share
+    /// transaction with statements JSON cannot express.
+    /// Set is never referenced in between, so it may be recreated there
+    pub(crate) fn detach_script(&self, set: &schema::Set) -> String {
+        let chain = format!(
+            "{} {} {}",
+            keyword(&set.family),
+            set.table,
+            self.chain_name(&set.name)
+        );
+
+        format!(
+            "add chain {chain} {{ {}; }}\nflush chain {chain}\n",
+            self.chain_properties()
+        )
+    }
+
+    /// Rule of `detach_script` chain matching the set
+    pub(crate) fn rule_script(&self, set: &schema::Set) -> String {
+        format!(
+            "add rule {} {} {} {}\n",
+            keyword(&set.family),
+            set.table,
+            self.chain_name(&set.name),
+            self.rule_statements(&set.name, &set.set_type)
+        )
+    }
+
+    /// Type, hook and priority of the chain in nft syntax
+    pub(crate) fn chain_properties(&self) -> String {
+        format!(
+            "type {} hook {} priority {}",
+            keyword(&self.chain_type),
+            keyword(&self.hook),
+            self.priority
+        )
+    }
+
+    /// Body of the rule in nft syntax, e.g. `ip daddr @set meta mark set 0x10`
+    pub(crate) fn rule_statements(
+        &self,
+        set_name: &str,
+        set_type: &schema::SetTypeValue,
+    ) -> String {
+        let (protocol, field) = self.address(set_type);
+        let mut statements = format!("{protocol} {field} @{set_name}");
+
+        if let Some(mark) = self.mark {
+            let _ = write!(statements, " meta mark set {mark:#x}");
+        }
+        if let Some(verdict) = &self.verdict {
+            let _ = write!(statements, " {verdict}");
+        }
+
+        statements
+    }
+
+    pub(crate) fn chain_name(&self, set_name: &str) -> String {
+        self.chain
+            .clone()
+            .unwrap_or_else(|| format!("hirkn_{set_name}"))
+    }
+
+    /// Protocol and field of matched address
+    fn address(&self, set_type: &schema::SetTypeValue) -> (&'static str, &'static str) {
+        let is_ipv6 = *set_type == schema::SetTypeValue::Single(schema::SetType::Ipv6Addr);
+        let protocol = if is_ipv6 { "ip6" } else { "ip" };
+        let field = if self.source_address {
+            "saddr"
+        } else {
+            "daddr"
+        };
+
+        (protocol, field)
+    }
+
+    fn chain(&self, set: &schema::Set) -> schema::Chain {
+        schema::Chain {
+            family: set.family,
+            table: set.table.clone(),
+            name: self.chain_name(&set.name),
+            newname: None,
+            handle: None,
+            _type: Some(self.chain_type.clone()),
+            hook: Some(self.hook.clone()),
+            prio: Some(self.priority),
+            dev: None,
+            policy: None,
+        }
+    }
+
+    fn rule(&self, set: &schema::Set) -> schema::Rule {
+        let (protocol, field) = self.address(&set.set_type);
+        let address = PayloadField {
+            protocol: protocol.to_string(),
+            field: field.to_string(),
+        };
+
+        let mut expr = vec![Statement::Match(Match {
+            left: Expression::Named(NamedExpression::Payload(Payload::PayloadField(address))),
+            right: Expression::String(format!("@{}", set.name)),
+            op: Operator::EQ,
+        })];
+
+        if let Some(mark) = self.mark {
+            expr.push(Statement::Mangle(Mangle {
+                key: Expression::Named(NamedExpression::Meta(Meta { key: MetaKey::Mark })),
+                value: Expression::Number(mark),
+            }));
+        }
+
+        if let Some(verdict) = &self.verdict {
+            expr.push(match verdict.clone() {
+                RoutingVerdict::Accept => Statement::Accept(None),
+                RoutingVerdict::Drop => Statement::Drop(None),
+                RoutingVerdict::Return => Statement::Return(None),
+                RoutingVerdict::Jump(target) => Statement::Jump(JumpTarget { target }),
+                RoutingVerdict::Goto(target) => Statement::Goto(JumpTarget { target }),
+            });
+        }
+
+        schema::Rule {
+            family: set.family,
+            table: set.table.clone(),
+            chain: self.chain_name(&set.name),
+            expr,
+            handle: None,
+            index: None,
+            comment: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{schema, RoutingOptions};
+    use nftables::types::NfFamily;
+    use serde_json::json;
+
+    #[test]
+    fn require_mark_or_verdict() {
+        let options: RoutingOptions = serde_yaml::from_str("source_address: true").unwrap();
+        assert!(options.validate("blocked").is_err());
+
+        let options: RoutingOptions = serde_yaml::from_str("verdict: drop").unwrap();
+        options.validate("blocked").unwrap();
+    }
+
+    #[test]
+    fn mark_rule() {
+        let options: RoutingOptions = serde_yaml::from_str("mark: 16\nverdict: accept").unwrap();
+        let set = schema::Set {
+            family: NfFamily::INet,
+            table: "fw4".to_string(),
+            name: "blocked".to_string(),
+            handle: None,
+            set_type: schema::SetTypeValue::Single(schema::SetType::Ipv4Addr),
+            policy: None,
+            flags: None,
+            elem: None,
+            timeout: None,
+            gc_interval: None,
+            size: None,
+        };
+
+        let script = options.detach_script(&set) + &options.rule_script(&set);
+        assert_eq!(
+            script,
+            "add chain inet fw4 hirkn_blocked { type filter hook prerouting priority -150; }\n\
+            flush chain inet fw4 hirkn_blocked\n\
+            add rule inet fw4 hirkn_blocked ip daddr @blocked meta mark set 0x10 accept\n"
+        );
+
+        let rule = options.rule(&set);
+        assert_eq!(rule.chain, "hirkn_blocked");
+        assert_eq!(
+            serde_json::to_value(&rule.expr).unwrap(),
+            json!([
+                {"match": {"left": {"payload": {"protocol": "ip", "field": "daddr"}}, "right": "@blocked", "op": "=="}},
+                {"mangle": {"key": {"meta": {"key": "mark"}}, "value": 16}},
+                {"accept": null},
+            ])
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use super::{FetchContext, IPParsable, SourceProvider, SourceUrl, IP};
+use anyhow::{anyhow, Result};
+use flate2::read::MultiGzDecoder;
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    io::Read,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use url::Url;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AsnDatasetFormat {
+    /// CAIDA Routeviews prefix-to-AS: `prefix<TAB>length<TAB>asn`
+    Pfx2as,
+    /// Simple `prefix asn` lines
+    #[default]
+    Text,
+    /// MRT `TABLE_DUMP_V2` RIB dump like RIPE RIS bview
+    Mrt,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct AsnOptions {
+    numbers: HashSet<u32>,
+    dataset: SourceUrl,
+    #[serde(default)]
+    format: AsnDatasetFormat,
+}
+
+impl AsnOptions {
+    /// Returns prefixes originated by configured AS numbers
+    /// and whether they changed since last download
+    pub(crate) async fn download(&self, context: &FetchContext) -> Result<(Vec<IP>, bool)> {
+        let key = self.cache_key();
+        let not_older_than = context.cache.get(&key).await;
+        let provider = SourceProvider::new(self.dataset.clone(), &context.http_client)?;
+
+        // Datasets are huge, so parse them only when changed
+        let last_good = context.cache.get_last_good(&key).await;
+        let modified = match (provider.check_modified(not_older_than).await?, last_good) {
+            (Some(modified), _) => modified,
+            (None, Some(last_good)) => return Ok((last_good, false)),
+            (None, None) => return Ok((vec![], false)),
+        };
+
+        let mut dataset = provider.fetch_bytes().await?;
+
+        // gzip magic
+        if dataset.starts_with(&[0x1f, 0x8b]) {
+            let mut decompressed = Vec::with_capacity(dataset.len() * 4);
+            MultiGzDecoder::new(dataset.as_slice()).read_to_end(&mut decompressed)?;
+            dataset = decompressed;
+        }
+
+        let prefixes = match self.format {
+            AsnDatasetFormat::Pfx2as | AsnDatasetFormat::Text => {
+                self.filter_text(&String::from_utf8_lossy(&dataset))
+            }
+            AsnDatasetFormat::Mrt => self.filter_mrt(&dataset)?,
+        };
+
+        let prefixes: Vec<IP> = prefixes.into_iter().map(IP::Network).collect();
+        log::info!(
+            "Found {} prefixes for {} AS numbers",
+            prefixes.len(),
+            self.numbers.len()
+        );
+
+        context.cache.set(&key, modified).await;
+        context.cache.set_last_good(&key, prefixes.clone()).await;
+
+        Ok((prefixes, true))
+    }
+
+    /// Sources may share dataset while filtering different AS numbers
+    fn cache_key(&self) -> Url {
+        let mut numbers: Vec<_> = self.numbers.iter().map(u32::to_string).collect();
+        numbers.sort_unstable();
+
+        let mut key = self.dataset.url.clone();
+        key.set_fragment(Some(&format!("asn={}", numbers.join(","))));
+        key
+    }
+
+    fn filter_text(&self, dataset: &str) -> HashSet<IpNet> {
+        dataset
+            .lines()
+            .filter_map(|line| match self.format {
+                AsnDatasetFormat::Pfx2as => parse_pfx2as_line(line),
+                _ => parse_text_line(line),
+            })
+            .filter(|(_, origins)| origins.iter().any(|asn| self.numbers.contains(asn)))
+            .map(|(prefix, _)| prefix)
+            .collect()
+    }
+
+    fn filter_mrt(&self, dataset: &[u8]) -> Result<HashSet<IpNet>> {
+        let mut prefixes = HashSet::new();
+
+        for record in MrtRecords(dataset) {
+            let Some((prefix, origins)) = record? else {
+                continue;
+            };
+
+            if origins.iter().any(|asn| self.numbers.contains(asn)) {
+                prefixes.insert(prefix);
+            }
+        }
+
+        Ok(prefixes)
+    }
+}
+
+fn parse_asn(asn: &str) -> Option<u32> {
+    asn.trim_start_matches("AS")
+        .trim_start_matches("as")
+        .parse()
+        .ok()
+}
+
+/// Multi-origin prefixes are separated by `_`, AS sets by `,`
+fn parse_pfx2as_line(line: &str) -> Option<(IpNet, Vec<u32>)> {
+    let mut fields = line.split_whitespace();
+
+    let address: IpAddr = fields.next()?.parse().ok()?;
+    let prefix_len = fields.next()?.parse().ok()?;
+    let prefix = IpNet::new(address, prefix_len).ok()?.trunc();
+
+    let origins = fields
+        .next()?
+        .split(['_', ','])
+        .filter_map(parse_asn)
+        .collect();
+
+    Some((prefix, origins))
+}
+
+fn parse_text_line(line: &str) -> Option<(IpNet, Vec<u32>)> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.split_whitespace();
+
+    let prefix = fields.next()?.parse::<IP>().ok()?.to_network().trunc();
+    let origin = parse_asn(fields.next()?)?;
+
+    Some((prefix, vec![origin]))
+}
+
+const MRT_TABLE_DUMP_V2: u16 = 13;
+const MRT_RIB_IPV4_UNICAST: u16 = 2;
+const MRT_RIB_IPV6_UNICAST: u16 = 4;
+const BGP_ATTR_EXTENDED_LENGTH: u8 = 0x10;
+const BGP_ATTR_AS_PATH: u8 = 2;
+const BGP_AS_SEQUENCE: u8 = 2;
+
+/// Iterates over RIB records of MRT dump yielding prefixes with their origin AS numbers.
+/// Records of other types are yielded as `None`
+struct MrtRecords<'a>(&'a [u8]);
+
+impl Iterator for MrtRecords<'_> {
+    type Item = Result<Option<(IpNet, Vec<u32>)>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+
+        let mut header = Cursor(self.0);
+        let record = (|| {
+            let _timestamp = header.u32()?;
+            let record_type = header.u16()?;
+            let subtype = header.u16()?;
+            let length = header.u32()? as usize;
+            let message = header.take(length)?;
+
+            Ok((record_type, subtype, message))
+        })();
+
+        let (record_type, subtype, message) = match record {
+            Ok(record) => record,
+            Err(error) => {
+                // Stop iteration after truncated record
+                self.0 = &[];
+                return Some(Err(error));
+            }
+        };
+
+        self.0 = header.0;
+
+        if record_type != MRT_TABLE_DUMP_V2 {
+            return Some(Ok(None));
+        }
+
+        let entry = match subtype {
+            MRT_RIB_IPV4_UNICAST => parse_rib_entry(message, false),
+            MRT_RIB_IPV6_UNICAST => parse_rib_entry(message, true),
+            _ => return Some(Ok(None)),
+        };
+
+        Some(entry.map(Some))
+    }
+}
+
+fn parse_rib_entry(message: &[u8], is_ipv6: bool) -> Result<(IpNet, Vec<u32>)> {
+    let mut message = Cursor(message);
+
+    let _sequence = message.u32()?;
+    let prefix_len = message.u8()?;
+    let prefix_bytes = message.take((usize::from(prefix_len) + 7) / 8)?;
+
+    let address = if is_ipv6 {
+        let mut octets = [0; 16];
+        octets
+            .get_mut(..prefix_bytes.len())
+            .ok_or_else(|| anyhow!("Invalid IPv6 prefix length {prefix_len}"))?
+            .copy_from_slice(prefix_bytes);
+        IpAddr::V6(Ipv6Addr::from(octets))
+    } else {
+        let mut octets = [0; 4];
+        octets
+            .get_mut(..prefix_bytes.len())
+            .ok_or_else(|| anyhow!("Invalid IPv4 prefix length {prefix_len}"))?
+            .copy_from_slice(prefix_bytes);
+        IpAddr::V4(Ipv4Addr::from(octets))
+    };
+
+    let prefix = IpNet::new(address, prefix_len)?.trunc();
+
+    let mut origins = vec![];
+    let entries_count = message.u16()?;
+    for _ in 0..entries_count {
+        let _peer_index = message.u16()?;
+        let _originated = message.u32()?;
+        let attributes_len = message.u16()?;
+        let attributes = message.take(usize::from(attributes_len))?;
+
+        origins.extend(parse_origins(attributes)?);
+    }
+
+    Ok((prefix, origins))
+}
+
+/// Origin is the last AS of path. When path ends with `AS_SET`, all its members are origins
+fn parse_origins(attributes: &[u8]) -> Result<Vec<u32>> {
+    let mut attributes = Cursor(attributes);
+
+    while !attributes.0.is_empty() {
+        let flags = attributes.u8()?;
+        let attribute_type = attributes.u8()?;
+        let length = if flags & BGP_ATTR_EXTENDED_LENGTH == 0 {
+            usize::from(attributes.u8()?)
+        } else {
+            usize::from(attributes.u16()?)
+        };
+        let value = attributes.take(length)?;
+
+        if attribute_type != BGP_ATTR_AS_PATH {
+            continue;
+        }
+
+        let mut path = Cursor(value);
+        let mut origins = vec![];
+
+        // TABLE_DUMP_V2 always uses 4-byte AS numbers
+        while !path.0.is_empty() {
+            let segment_type = path.u8()?;
+            let count = path.u8()?;
+            let numbers = (0..count).map(|_| path.u32()).collect::<Result<Vec<_>>>()?;
+
+            origins = if segment_type == BGP_AS_SEQUENCE {
+                numbers.last().copied().into_iter().collect()
+            } else {
+                numbers
+            };
+        }
+
+        return Ok(origins);
+    }
+
+    Ok(vec![])
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(anyhow!("MRT dump is truncated"));
+        }
+
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_pfx2as_line, parse_text_line, AsnOptions, MrtRecords};
+
+    #[test]
+    fn cache_key_depends_on_numbers() {
+        let options = |numbers: &str| -> AsnOptions {
+            let yaml = format!("numbers: {numbers}\ndataset: https://example.com/pfx2as.gz");
+            serde_yaml::from_str(&yaml).unwrap()
+        };
+
+        assert_eq!(
+            options("[13335, 32934]").cache_key(),
+            options("[32934, 13335]").cache_key()
+        );
+        assert_ne!(
+            options("[13335]").cache_key(),
+            options("[32934]").cache_key()
+        );
+    }
+
+    #[test]
+    fn parse_text_datasets() {
+        let (prefix, origins) = parse_pfx2as_line("1.0.0.0\t24\t13335").unwrap();
+        assert_eq!(prefix.to_string(), "1.0.0.0/24");
+        assert_eq!(origins, [13335]);
+
+        let (_, origins) = parse_pfx2as_line("1.0.4.0\t22\t38803_56203,7545").unwrap();
+        assert_eq!(origins, [38803, 56203, 7545]);
+
+        let (prefix, origins) = parse_text_line("2a03:2880::/32 AS32934").unwrap();
+        assert_eq!(prefix.to_string(), "2a03:2880::/32");
+        assert_eq!(origins, [32934]);
+
+        assert!(parse_text_line("# comment").is_none());
+    }
+
+    #[test]
+    fn parse_mrt_rib_record() {
+        #[rustfmt::skip]
+        let message: Vec<u8> = [
+            &[0, 0, 0, 1][..],      // sequence
+            &[24, 1, 0, 0],         // 1.0.0.0/24
+            &[0, 1],                // entries count
+            &[0, 0],                // peer index
+            &[0, 0, 0, 0],          // originated time
+            &[0, 14],               // attributes length
+            &[0x40, 2, 10],         // AS_PATH
+            &[2, 2],                // AS_SEQUENCE of 2
+            &[0, 0, 0x0d, 0x1c],    // 3356
+            &[0, 0, 0x34, 0x17],    // 13335
+            &[0x40, 1, 1, 0],       // unused ORIGIN attribute
+        ]
+        .concat();
+
+        let mut record = vec![0, 0, 0, 0, 0, 13, 0, 2];
+        record.extend(u32::try_from(message.len()).unwrap().to_be_bytes());
+        record.extend(message);
+
+        let records: Vec<_> = MrtRecords(&record).collect();
+        assert_eq!(records.len(), 1);
+
+        let (prefix, origins) = records[0].as_ref().unwrap().clone().unwrap();
+        assert_eq!(prefix.to_string(), "1.0.0.0/24");
+        assert_eq!(origins, [13335]);
+    }
+}
@@ -1,13 +1,40 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
-use tokio::sync::RwLock;
+use super::{SetElement, IP};
+use crate::util::write_atomically;
+use std::{collections::HashMap, fs, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::{Mutex, RwLock};
 use url::Url;
 
 #[derive(Clone, Default)]
 pub(crate) struct Cache {
     states: Arc<RwLock<HashMap<Url, Duration>>>,
+    last_good: Arc<RwLock<HashMap<Url, Vec<IP>>>>,
+    entries_counts: Arc<RwLock<HashMap<String, usize>>>,
+    applied: Arc<RwLock<HashMap<String, Vec<SetElement>>>>,
+    /// Entries counts are kept there between runs
+    counts_path: Option<PathBuf>,
+    /// Keeps writes of counts file in order of their snapshots
+    counts_file: Arc<Mutex<()>>,
 }
 
 impl Cache {
+    /// Cache which keeps entries counts in file, so guards
+    /// compare lists with previous update of one-shot runs too
+    pub(crate) fn with_counts_file(path: PathBuf) -> Self {
+        let counts = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|error| {
+                log::warn!("Ignoring broken {}: {error}", path.display());
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        Self {
+            entries_counts: Arc::new(RwLock::new(counts)),
+            counts_path: Some(path),
+            ..Self::default()
+        }
+    }
+
     pub(crate) async fn get(&self, url: &Url) -> Option<Duration> {
         let states = self.states.read().await;
         states.get(url).map(ToOwned::to_owned)
@@ -17,4 +44,80 @@ impl Cache {
         let mut states = self.states.write().await;
         states.insert(url.clone(), timestamp);
     }
+
+    pub(crate) async fn get_last_good(&self, url: &Url) -> Option<Vec<IP>> {
+        let last_good = self.last_good.read().await;
+        last_good.get(url).map(ToOwned::to_owned)
+    }
+
+    pub(crate) async fn set_last_good(&self, url: &Url, entries: Vec<IP>) {
+        let mut last_good = self.last_good.write().await;
+        last_good.insert(url.clone(), entries);
+    }
+
+    pub(crate) async fn get_entries_count(&self, set_name: &str) -> Option<usize> {
+        let entries_counts = self.entries_counts.read().await;
+        entries_counts.get(set_name).copied()
+    }
+
+    pub(crate) async fn set_entries_count(&self, set_name: &str, count: usize) {
+        self.entries_counts
+            .write()
+            .await
+            .insert(set_name.to_string(), count);
+
+        let Some(path) = &self.counts_path else {
+            return;
+        };
+
+        let _file = self.counts_file.lock().await;
+        let contents = serde_json::to_string(&*self.entries_counts.read().await);
+
+        // File is written without holding counts, so other sets are not blocked by disk
+        let target = path.clone();
+        let stored = tokio::task::spawn_blocking(move || {
+            target.parent().map_or(Ok(()), fs::create_dir_all)?;
+            write_atomically(&target, &contents?)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|stored| stored);
+
+        if let Err(error) = stored {
+            log::warn!("Cannot store entries counts to {}: {error}", path.display());
+        }
+    }
+
+    /// Contents of the set after last successful update
+    pub(crate) async fn get_applied(&self, set_name: &str) -> Option<Vec<SetElement>> {
+        let applied = self.applied.read().await;
+        applied.get(set_name).map(ToOwned::to_owned)
+    }
+
+    pub(crate) async fn set_applied(&self, set_name: &str, elements: Vec<SetElement>) {
+        let mut applied = self.applied.write().await;
+        applied.insert(set_name.to_string(), elements);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+
+    #[tokio::test]
+    async fn counts_survive_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("hirkn_counts_{}", std::process::id()))
+            .join("counts.json");
+
+        let cache = Cache::with_counts_file(path.clone());
+        cache.set_entries_count("blocked", 42).await;
+
+        let restarted = Cache::with_counts_file(path.clone());
+        let count = restarted.get_entries_count("blocked").await;
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(count, Some(42));
+    }
 }
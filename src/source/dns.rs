@@ -0,0 +1,156 @@
+use anyhow::Result;
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub(crate) struct DnsOptions {
+    servers: Vec<IpAddr>,
+    /// TLS name of servers. When presented, DNS-over-HTTPS is used
+    over_https: Option<String>,
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+#[derive(Clone)]
+pub(crate) struct DnsResolver {
+    resolver: Option<TokioAsyncResolver>,
+    /// Resolver reading system config, used for lookups needing TTL
+    system: Option<TokioAsyncResolver>,
+    hosts: Arc<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl DnsResolver {
+    pub(crate) fn new(options: DnsOptions) -> Self {
+        let resolver = (!options.servers.is_empty()).then(|| {
+            let servers = match options.over_https {
+                Some(tls_name) => {
+                    NameServerConfigGroup::from_ips_https(&options.servers, 443, tls_name, true)
+                }
+                None => NameServerConfigGroup::from_ips_clear(&options.servers, 53, true),
+            };
+
+            let config = ResolverConfig::from_parts(None, vec![], servers);
+            TokioAsyncResolver::tokio(config, ResolverOpts::default())
+        });
+
+        let system = match &resolver {
+            Some(_) => None,
+            None => TokioAsyncResolver::tokio_from_system_conf()
+                .map_err(|error| log::warn!("Cannot read system DNS config: {error}"))
+                .ok(),
+        };
+
+        Self {
+            resolver,
+            system,
+            hosts: Arc::new(options.hosts),
+        }
+    }
+
+    /// Whether system resolver should be replaced
+    pub(crate) fn is_custom(&self) -> bool {
+        self.resolver.is_some() || !self.hosts.is_empty()
+    }
+
+    pub(crate) async fn lookup(&self, name: &str) -> Result<Vec<IpAddr>> {
+        let (addresses, _) = self.lookup_with_ttl(name).await?;
+        Ok(addresses)
+    }
+
+    /// TTL is unknown for static hosts entries and when system DNS config
+    /// is unreadable, so lookups fall back to the OS resolver
+    pub(crate) async fn lookup_with_ttl(
+        &self,
+        name: &str,
+    ) -> Result<(Vec<IpAddr>, Option<Duration>)> {
+        if let Some(addresses) = self.hosts.get(name) {
+            return Ok((addresses.clone(), None));
+        }
+
+        let Some(resolver) = self.resolver.as_ref().or(self.system.as_ref()) else {
+            let addresses = tokio::net::lookup_host((name, 0)).await?;
+            return Ok((addresses.map(|address| address.ip()).collect(), None));
+        };
+
+        let lookup = resolver.lookup_ip(name).await?;
+        let ttl = lookup
+            .valid_until()
+            .saturating_duration_since(Instant::now());
+
+        Ok((lookup.into_iter().collect(), Some(ttl)))
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let addresses = resolver.lookup(name.as_str()).await?;
+
+            // Port is ignored by reqwest and replaced with the one from url
+            let addresses: Addrs = Box::new(
+                addresses
+                    .into_iter()
+                    .map(|address| SocketAddr::new(address, 0)),
+            );
+
+            Ok(addresses)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DnsOptions, DnsResolver};
+    use reqwest::dns::Resolve;
+    use std::net::IpAddr;
+
+    #[test]
+    fn parse_dns_over_https() {
+        let options: DnsOptions =
+            serde_yaml::from_str("servers: [1.1.1.1, 1.0.0.1]\nover_https: cloudflare-dns.com")
+                .unwrap();
+
+        assert_eq!(options.servers.len(), 2);
+        assert_eq!(options.over_https.as_deref(), Some("cloudflare-dns.com"));
+    }
+
+    #[tokio::test]
+    async fn static_hosts_override() {
+        let options: DnsOptions =
+            serde_yaml::from_str("hosts: {mirror.example.com: [192.0.2.1, '2001:db8::1']}")
+                .unwrap();
+        let resolver = DnsResolver::new(options);
+        assert!(resolver.is_custom());
+
+        let (addresses, ttl) = resolver
+            .lookup_with_ttl("mirror.example.com")
+            .await
+            .unwrap();
+
+        let expected: Vec<IpAddr> =
+            vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+        assert_eq!(addresses, expected);
+        assert_eq!(ttl, None);
+
+        // reqwest goes through the same overrides
+        let through_reqwest: Vec<IpAddr> = resolver
+            .resolve("mirror.example.com".parse().unwrap())
+            .await
+            .unwrap()
+            .map(|address| address.ip())
+            .collect();
+        assert_eq!(through_reqwest, expected);
+    }
+}
@@ -0,0 +1,146 @@
+use super::{
+    source_provider::list_entries, DnsResolver, FetchContext, IPParsable, SourceProvider,
+    SourceUrl, IP,
+};
+use anyhow::Result;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub(crate) struct ResolveOptions {
+    /// Maximum number of simultaneous lookups
+    concurrency: usize,
+    #[serde(with = "humantime_serde")]
+    timeout: Duration,
+    /// Use DNS TTL as element timeout. Set must have `timeout` flag for this
+    ttl_timeouts: bool,
+}
+
+impl Default for ResolveOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            timeout: Duration::from_secs(5),
+            ttl_timeouts: false,
+        }
+    }
+}
+
+impl ResolveOptions {
+    /// Returns resolved addresses with their timeouts in seconds
+    pub(crate) async fn resolve(
+        &self,
+        domains: HashSet<String>,
+        dns_resolver: &DnsResolver,
+    ) -> HashMap<IP, Option<u32>> {
+        let semaphore = Arc::new(Semaphore::new(self.concurrency.max(1)));
+        let mut lookups = JoinSet::new();
+
+        for domain in domains {
+            let resolver = dns_resolver.clone();
+            let semaphore = semaphore.clone();
+            let timeout = self.timeout;
+
+            lookups.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let lookup = resolver.lookup_with_ttl(&domain);
+                let lookup = tokio::time::timeout(timeout, lookup).await;
+                (domain, lookup)
+            });
+        }
+
+        let mut addresses_timeouts = HashMap::new();
+
+        while let Some(task) = lookups.join_next().await {
+            let Ok((domain, lookup)) = task else {
+                continue;
+            };
+
+            let (addresses, ttl) = match lookup {
+                Ok(Ok(resolved)) => resolved,
+                Ok(Err(error)) => {
+                    log::debug!("Cannot resolve {domain}: {error}");
+                    continue;
+                }
+                Err(_) => {
+                    log::debug!("Timed out while resolving {domain}");
+                    continue;
+                }
+            };
+
+            let timeout = ttl
+                .filter(|_| self.ttl_timeouts)
+                .map(|ttl| u32::try_from(ttl.as_secs()).unwrap_or(u32::MAX).max(1));
+
+            for address in addresses {
+                // Address shared by several domains lives as long as the longest of them
+                addresses_timeouts
+                    .entry(IP::Single(address))
+                    .and_modify(|existing: &mut Option<u32>| {
+                        *existing = existing.zip(timeout).map(|(lhs, rhs)| lhs.max(rhs));
+                    })
+                    .or_insert(timeout);
+            }
+        }
+
+        addresses_timeouts
+    }
+}
+
+/// Domain lists are always downloaded as addresses behind them may change at any time
+pub(crate) async fn download_domains(
+    urls: &[SourceUrl],
+    context: &FetchContext,
+) -> Result<HashSet<String>> {
+    let mut domains = HashSet::new();
+
+    for url in urls {
+        let provider = SourceProvider::new(url.clone(), &context.http_client)?;
+        let raw_list = provider.fetch_raw().await?;
+
+        domains.extend(list_entries(&raw_list).filter_map(parse_domain));
+    }
+
+    Ok(domains)
+}
+
+fn parse_domain(entry: &str) -> Option<String> {
+    let domain = entry
+        .trim_start_matches("*.")
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+
+    let is_valid = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+        });
+
+    is_valid.then_some(domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_domain;
+
+    #[test]
+    fn parse_domains() {
+        assert_eq!(parse_domain("Example.COM"), Some("example.com".to_string()));
+        assert_eq!(
+            parse_domain("*.example.com."),
+            Some("example.com".to_string())
+        );
+        assert_eq!(parse_domain("localhost"), None);
+        assert_eq!(parse_domain("exa mple.com"), None);
+        assert_eq!(parse_domain("example..com"), None);
+    }
+}
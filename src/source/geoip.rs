@@ -0,0 +1,123 @@
+use super::{FetchContext, IPParsable, SourceProvider, IP};
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use ipnetwork::IpNetwork;
+use maxminddb::{geoip2, Reader};
+use serde::Deserialize;
+use std::{collections::HashSet, path::PathBuf};
+use url::Url;
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct GeoIpOptions {
+    /// Country or city database in `.mmdb` format
+    database: PathBuf,
+    /// ISO 3166-1 alpha-2 codes, e.g. `NL`
+    countries: HashSet<String>,
+}
+
+impl GeoIpOptions {
+    /// Returns networks located in configured countries
+    /// and whether they changed since last download
+    pub(crate) async fn download(&self, context: &FetchContext) -> Result<(Vec<IP>, bool)> {
+        let url = Url::from_file_path(&self.database)
+            .map_err(|()| anyhow!("Database path {} is not absolute", self.database.display()))?;
+        let provider = SourceProvider::new(url.clone().into(), &context.http_client)?;
+
+        // Sources may share database while filtering different countries
+        let mut countries: Vec<_> = self
+            .countries
+            .iter()
+            .map(|code| code.to_ascii_uppercase())
+            .collect();
+        countries.sort_unstable();
+
+        let mut key = url;
+        key.set_fragment(Some(&format!("countries={}", countries.join(","))));
+        let not_older_than = context.cache.get(&key).await;
+
+        // Walking the whole database is expensive, so do it only when it is changed
+        let last_good = context.cache.get_last_good(&key).await;
+        let modified = match (provider.check_modified(not_older_than).await?, last_good) {
+            (Some(modified), _) => modified,
+            (None, Some(last_good)) => return Ok((last_good, false)),
+            (None, None) => return Ok((vec![], false)),
+        };
+
+        let reader = Reader::from_source(provider.fetch_bytes().await?)?;
+        let networks = self.find_networks(&reader)?;
+
+        log::info!(
+            "Found {} networks for {} countries",
+            networks.len(),
+            self.countries.len()
+        );
+
+        context.cache.set(&key, modified).await;
+        context.cache.set_last_good(&key, networks.clone()).await;
+
+        Ok((networks, true))
+    }
+
+    fn find_networks(&self, reader: &Reader<Vec<u8>>) -> Result<Vec<IP>> {
+        // IPv6 databases contain IPv4 subtree too and report it as IPv4 networks
+        let root = if reader.metadata.ip_version == 6 {
+            IpNetwork::V6("::/0".parse()?)
+        } else {
+            IpNetwork::V4("0.0.0.0/0".parse()?)
+        };
+
+        let mut networks = vec![];
+
+        for item in reader.within::<geoip2::Country>(root)? {
+            let item = item?;
+
+            // Anycast and satellite networks have no physical country
+            let country = item.info.country.or(item.info.registered_country);
+            let Some(iso_code) = country.and_then(|country| country.iso_code) else {
+                continue;
+            };
+
+            if self
+                .countries
+                .iter()
+                .any(|code| code.eq_ignore_ascii_case(iso_code))
+            {
+                networks.push(IpNet::new(item.ip_net.ip(), item.ip_net.prefix())?);
+            }
+        }
+
+        // Databases split networks by city and provider, so merge adjacent ones back
+        let networks = IpNet::aggregate(&networks)
+            .into_iter()
+            .map(IP::Network)
+            .collect();
+
+        Ok(networks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeoIpOptions;
+    use crate::source::IP;
+    use maxminddb::Reader;
+    use std::collections::HashSet;
+
+    /// IPv4 database: 0.0.0.0/2 is in NL, 64.0.0.0/2 is in DE
+    /// and 128.0.0.0/1 is registered in NL
+    const DATABASE: &[u8] = include_bytes!("testdata/countries.mmdb");
+
+    #[test]
+    fn find_country_networks() {
+        let options = GeoIpOptions {
+            database: "/var/lib/hirkn/countries.mmdb".into(),
+            countries: HashSet::from(["nl".to_string()]),
+        };
+
+        let reader = Reader::from_source(DATABASE.to_vec()).unwrap();
+        let networks = options.find_networks(&reader).unwrap();
+
+        let expected: Vec<IP> = vec!["0.0.0.0/2".parse().unwrap(), "128.0.0.0/1".parse().unwrap()];
+        assert_eq!(networks, expected);
+    }
+}
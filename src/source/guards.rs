@@ -0,0 +1,214 @@
+use super::IP;
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+/// Private, reserved and special-purpose ranges which should never be routed somewhere
+static BOGONS: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "::ffff:0:0/96",
+    "100::/64",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub(crate) struct Guards {
+    min_entries: Option<usize>,
+    /// Maximum allowed shrink relative to previous update. E.g. 0.5 for 50%
+    max_shrink: Option<f64>,
+    /// Maximum allowed growth relative to previous update. E.g. 1.0 for 100%
+    max_growth: Option<f64>,
+    /// Entries covering any of these networks are forbidden. E.g. 0.0.0.0/0
+    forbidden_networks: Vec<IP>,
+    min_ipv4_prefix_len: Option<u8>,
+    min_ipv6_prefix_len: Option<u8>,
+    reject_bogons: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct GuardViolation(String);
+
+impl Display for GuardViolation {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "Sanity guard violated: {}", self.0)
+    }
+}
+
+impl std::error::Error for GuardViolation {}
+
+impl Guards {
+    pub(crate) fn check<'a>(
+        &self,
+        entries: impl ExactSizeIterator<Item = &'a IP>,
+        previous: Option<usize>,
+    ) -> Result<(), GuardViolation> {
+        self.check_count(entries.len(), previous)?;
+
+        let bogons: Vec<IpNet> = if self.reject_bogons {
+            BOGONS
+                .iter()
+                .filter_map(|bogon| bogon.parse().ok())
+                .collect()
+        } else {
+            vec![]
+        };
+
+        for entry in entries {
+            self.check_entry(&entry.to_network(), &bogons)?;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn check_count(&self, count: usize, previous: Option<usize>) -> Result<(), GuardViolation> {
+        if let Some(min_entries) = self.min_entries {
+            if count < min_entries {
+                return Err(GuardViolation(format!(
+                    "got {count} entries while at least {min_entries} are required"
+                )));
+            }
+        }
+
+        let Some(previous) = previous.filter(|previous| *previous != 0) else {
+            return Ok(());
+        };
+
+        let change = (count as f64 - previous as f64) / previous as f64;
+
+        if let Some(max_shrink) = self.max_shrink {
+            if -change > max_shrink {
+                return Err(GuardViolation(format!(
+                    "list shrunk from {previous} to {count} entries"
+                )));
+            }
+        }
+
+        if let Some(max_growth) = self.max_growth {
+            if change > max_growth {
+                return Err(GuardViolation(format!(
+                    "list grew from {previous} to {count} entries"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_entry(&self, network: &IpNet, bogons: &[IpNet]) -> Result<(), GuardViolation> {
+        let min_prefix_len = match network {
+            IpNet::V4(_) => self.min_ipv4_prefix_len,
+            IpNet::V6(_) => self.min_ipv6_prefix_len,
+        };
+
+        if let Some(min_prefix_len) = min_prefix_len {
+            if network.prefix_len() < min_prefix_len {
+                return Err(GuardViolation(format!(
+                    "{network} is shorter than /{min_prefix_len}"
+                )));
+            }
+        }
+
+        if let Some(forbidden) = self
+            .forbidden_networks
+            .iter()
+            .map(IP::to_network)
+            .find(|forbidden| network.contains(forbidden))
+        {
+            return Err(GuardViolation(format!(
+                "{network} covers forbidden {forbidden}"
+            )));
+        }
+
+        if let Some(bogon) = bogons
+            .iter()
+            .find(|bogon| bogon.contains(network) || network.contains(*bogon))
+        {
+            return Err(GuardViolation(format!(
+                "{network} overlaps private or reserved {bogon}"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Guards;
+    use crate::source::IP;
+
+    fn entries(list: &[&str]) -> Vec<IP> {
+        list.iter().map(|entry| entry.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn count_limits() {
+        let guards = Guards {
+            min_entries: Some(2),
+            max_shrink: Some(0.5),
+            max_growth: Some(1.0),
+            ..Guards::default()
+        };
+
+        let list = entries(&["1.1.1.1", "8.8.8.8", "9.9.9.9"]);
+
+        assert!(guards.check(list.iter(), None).is_ok());
+        assert!(guards.check(list.iter(), Some(4)).is_ok());
+        assert!(guards.check(list[..1].iter(), None).is_err());
+        assert!(guards.check(list.iter(), Some(10)).is_err());
+        assert!(guards.check(list.iter(), Some(1)).is_err());
+    }
+
+    #[test]
+    fn forbidden_entries() {
+        let guards = Guards {
+            forbidden_networks: vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+            min_ipv4_prefix_len: Some(8),
+            ..Guards::default()
+        };
+
+        assert!(guards
+            .check(entries(&["1.0.0.0/8", "::1"]).iter(), None)
+            .is_ok());
+        assert!(guards.check(entries(&["0.0.0.0/0"]).iter(), None).is_err());
+        assert!(guards.check(entries(&["::/0"]).iter(), None).is_err());
+        assert!(guards.check(entries(&["1.0.0.0/7"]).iter(), None).is_err());
+    }
+
+    #[test]
+    fn bogon_entries() {
+        let guards = Guards {
+            reject_bogons: true,
+            ..Guards::default()
+        };
+
+        assert!(guards
+            .check(entries(&["1.1.1.1", "2a00::/16"]).iter(), None)
+            .is_ok());
+        assert!(guards
+            .check(entries(&["192.168.1.1"]).iter(), None)
+            .is_err());
+        assert!(guards.check(entries(&["fd00::1"]).iter(), None).is_err());
+        assert!(guards.check(entries(&["0.0.0.0/1"]).iter(), None).is_err());
+    }
+}
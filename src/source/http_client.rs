@@ -0,0 +1,224 @@
+use super::DnsResolver;
+use anyhow::Result;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, Proxy, RequestBuilder,
+};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use url::Url;
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BindOptions {
+    address: Option<IpAddr>,
+    interface: Option<String>,
+    /// Socket mark (`SO_MARK`) is dropped, as reqwest gives no access
+    /// to sockets before they connect. The key is kept to explain that
+    #[serde(deserialize_with = "reject_mark")]
+    mark: (),
+}
+
+fn reject_mark<'de, D: Deserializer<'de>>(_: D) -> Result<(), D::Error> {
+    Err(de::Error::custom(
+        "socket mark is not supported, bind `address` and route it with `ip rule from` instead",
+    ))
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub(crate) struct HttpOptions {
+    #[serde(with = "humantime_serde")]
+    connect_timeout: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    read_timeout: Option<Duration>,
+    proxy: Option<Url>,
+    user_agent: Option<String>,
+    headers: HashMap<String, String>,
+    bind: BindOptions,
+}
+
+impl HttpOptions {
+    fn build_client(&self, bind: &BindOptions, dns: &DnsResolver) -> Result<Client> {
+        let mut builder = Client::builder();
+
+        if dns.is_custom() {
+            builder = builder.dns_resolver(Arc::new(dns.clone()));
+        }
+
+        if let Some(address) = bind.address {
+            builder = builder.local_address(address);
+        }
+
+        if let Some(interface) = &bind.interface {
+            builder = builder.interface(interface);
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        let mut headers = HeaderMap::with_capacity(self.headers.len());
+        for (name, value) in &self.headers {
+            headers.insert(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+        }
+
+        Ok(builder.default_headers(headers).build()?)
+    }
+}
+
+/// Shares one client between all urls. Urls with own bind options
+/// get separate clients which are created once and reused too.
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    options: Arc<HttpOptions>,
+    dns: DnsResolver,
+    shared: Client,
+    bound: Arc<Mutex<HashMap<BindOptions, Client>>>,
+}
+
+impl HttpClient {
+    pub(crate) fn new(options: HttpOptions, dns: DnsResolver) -> Result<Self> {
+        let shared = options.build_client(&options.bind, &dns)?;
+
+        Ok(Self {
+            options: Arc::new(options),
+            dns,
+            shared,
+            bound: Arc::default(),
+        })
+    }
+
+    pub(crate) fn client_for(&self, bind: Option<&BindOptions>) -> Result<Client> {
+        let Some(bind) = bind else {
+            return Ok(self.shared.clone());
+        };
+
+        let mut bound = self.bound.lock().expect("Http clients lock is poisoned");
+        if let Some(client) = bound.get(bind) {
+            return Ok(client.clone());
+        }
+
+        let client = self.options.build_client(bind, &self.dns)?;
+        bound.insert(bind.clone(), client.clone());
+
+        Ok(client)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Auth {
+    Bearer(String),
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub(crate) struct RequestOptions {
+    headers: HashMap<String, String>,
+    auth: Option<Auth>,
+}
+
+impl RequestOptions {
+    pub(crate) fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        // Invalid header names or values are not checked here,
+        // reqwest will report them when request is sent
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        match &self.auth {
+            Some(Auth::Bearer(token)) => request.bearer_auth(token),
+            Some(Auth::Basic { username, password }) => {
+                request.basic_auth(username, password.as_ref())
+            }
+            None => request,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Auth, BindOptions, HttpOptions};
+    use crate::source::SourceUrl;
+    use reqwest::{header::AUTHORIZATION, Client};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_http_options() {
+        let options: HttpOptions = serde_yaml::from_str(
+            "connect_timeout: 5s\n\
+            read_timeout: 1m\n\
+            proxy: socks5h://127.0.0.1:1080\n\
+            user_agent: hirkn\n\
+            headers: {X-Client: router}\n\
+            bind: {interface: wg0}",
+        )
+        .unwrap();
+
+        assert_eq!(options.connect_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(options.read_timeout, Some(Duration::from_secs(60)));
+        assert_eq!(options.proxy.unwrap().scheme(), "socks5h");
+        assert_eq!(options.user_agent.as_deref(), Some("hirkn"));
+        assert_eq!(options.headers["X-Client"], "router");
+        assert_eq!(options.bind.interface.as_deref(), Some("wg0"));
+    }
+
+    #[test]
+    fn reject_socket_mark() {
+        let error = serde_yaml::from_str::<BindOptions>("interface: wg0\nmark: 16").unwrap_err();
+        assert!(error.to_string().contains("socket mark is not supported"));
+    }
+
+    #[test]
+    fn apply_url_headers_and_auth() {
+        let url: SourceUrl = serde_yaml::from_str(
+            "url: https://example.com/list.txt\n\
+            headers: {X-Token: secret}\n\
+            auth: {basic: {username: user, password: pass}}",
+        )
+        .unwrap();
+        assert!(matches!(url.request.auth, Some(Auth::Basic { .. })));
+
+        let request = url
+            .request
+            .apply(Client::new().get(url.url.clone()))
+            .build()
+            .unwrap();
+
+        assert_eq!(request.headers()["X-Token"], "secret");
+        assert_eq!(request.headers()[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+
+        let url: SourceUrl =
+            serde_yaml::from_str("url: https://example.com/list.txt\nauth: {bearer: token}")
+                .unwrap();
+        let request = url
+            .request
+            .apply(Client::new().get(url.url.clone()))
+            .build()
+            .unwrap();
+
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer token");
+    }
+}
@@ -1,20 +1,40 @@
+mod asn;
 mod cache;
+mod dns;
+mod domains;
+mod geoip;
+mod guards;
+mod http_client;
 mod source_provider;
-
-use self::source_provider::FetchStatus;
+mod source_url;
+mod verify;
+
+use self::{
+    asn::AsnOptions,
+    domains::{download_domains, ResolveOptions},
+    geoip::GeoIpOptions,
+    guards::Guards,
+    source_provider::{list_entries, FetchStatus},
+    verify::VerificationError,
+};
 pub(crate) use self::{
     cache::Cache as SourcesCache,
-    source_provider::{IPParsable, SourceProvider, IP},
+    dns::{DnsOptions, DnsResolver},
+    guards::GuardViolation,
+    http_client::{HttpClient, HttpOptions},
+    source_provider::{IPParsable, SetElement, SourceProvider, IP},
+    source_url::SourceUrl,
 };
+use crate::nf_helpers::{MapTarget, RoutingOptions};
 use anyhow::Result;
-use nftables::{expr::Expression, schema, types};
+use nftables::{schema, types};
 use serde::Deserialize;
 use std::{
     collections::{HashSet, LinkedList},
     sync::Arc,
+    time::Duration,
 };
 use tokio::task::JoinSet;
-use url::Url;
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(default)]
@@ -26,6 +46,20 @@ pub(crate) struct SetTemplate {
     pub(crate) flags: Option<HashSet<schema::SetFlag>>,
     pub(crate) timeout: Option<u32>,
     pub(crate) gc_interval: Option<u32>,
+    /// Count packets and bytes matched by each element
+    pub(crate) counter: bool,
+    /// Maximum number of elements. Used only by nftables backend
+    pub(crate) size: Option<SetSize>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SetSize {
+    /// Computed from number of loaded elements.
+    /// Set is recreated when it must grow
+    Auto,
+    #[serde(untagged)]
+    Fixed(u32),
 }
 
 impl Default for SetTemplate {
@@ -37,71 +71,251 @@ impl Default for SetTemplate {
             flags: Some([schema::SetFlag::Interval].into()),
             timeout: None,
             gc_interval: None,
+            counter: false,
+            size: None,
         }
     }
 }
 
+impl SetTemplate {
+    /// Default timeout makes nft add the flag on its own
+    pub(crate) fn has_timeouts(&self) -> bool {
+        self.timeout.is_some()
+            || self
+                .flags
+                .as_ref()
+                .is_some_and(|flags| flags.contains(&schema::SetFlag::Timeout))
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SourceFormat {
+    /// IPv4/IPv6 addresses and CIDR-notated subnets
+    #[default]
+    Ips,
+    /// Hostnames to be resolved
+    Domains,
+}
+
 #[derive(Deserialize, Debug)]
 pub(crate) struct Source {
     pub(crate) set_name: String,
     #[serde(default)]
     pub(crate) set_template: SetTemplate,
-    pub(crate) urls: Vec<Url>,
+    #[serde(default)]
+    pub(crate) urls: Vec<SourceUrl>,
+    /// Addresses and subnets specified right in config
+    #[serde(default)]
+    pub(crate) entries: HashSet<IP>,
     pub(crate) entries_limit: usize,
+    #[serde(default)]
+    pub(crate) guards: Guards,
+    #[serde(default)]
+    pub(crate) format: SourceFormat,
+    #[serde(default)]
+    pub(crate) resolve: ResolveOptions,
+    /// Domains are handed to dnsmasq which fills the set itself
+    #[serde(default)]
+    pub(crate) dnsmasq: bool,
+    /// Prefixes originated by autonomous systems
+    pub(crate) asn: Option<AsnOptions>,
+    /// Networks located in specific countries
+    pub(crate) geoip: Option<GeoIpOptions>,
+    /// Overrides network namespace of config
+    pub(crate) netns: Option<String>,
+    /// Chain marking or redirecting traffic to the set
+    pub(crate) routing: Option<RoutingOptions>,
+    /// Fill the map instead of own set
+    pub(crate) map: Option<MapTarget>,
+    /// Elements expire unless updates keep refreshing them.
+    /// Set must have `timeout` flag
+    #[serde(default, with = "humantime_serde")]
+    pub(crate) element_timeout: Option<Duration>,
+    /// Comment elements with url or part of config they came from.
+    /// Ignored by ipset backend
+    #[serde(default)]
+    pub(crate) comments: bool,
+}
+
+/// Everything shared between sources during single update
+#[derive(Clone)]
+pub(crate) struct FetchContext {
+    pub(crate) cache: SourcesCache,
+    pub(crate) excluded: Arc<HashSet<IP>>,
+    pub(crate) http_client: HttpClient,
+    pub(crate) dns_resolver: DnsResolver,
 }
 
 impl Source {
-    pub(crate) async fn download_list(
-        &self,
-        cache: SourcesCache,
-        excluded: Arc<HashSet<IP>>,
-    ) -> Result<Vec<Expression>> {
-        if self.urls.len() == 1 {
-            self.download_single_list(cache, excluded).await
-        } else {
-            self.download_multiple_lists(cache, excluded).await
+    pub(crate) async fn download_list(&self, context: &FetchContext) -> Result<Vec<SetElement>> {
+        let Some(elements) = self.collect_elements(context, self.comments).await? else {
+            log::info!("Set {} is up to date", self.set_name);
+            return Ok(vec![]);
+        };
+
+        // Empty list is checked too, as it is the most common failure of mirrors
+        let previous = context.cache.get_entries_count(&self.set_name).await;
+        let ips = elements.iter().map(|element| &element.ip);
+        self.guards.check(ips, previous)?;
+
+        // Empty lists are never applied, so they are not the next baseline
+        if !elements.is_empty() {
+            context
+                .cache
+                .set_entries_count(&self.set_name, elements.len())
+                .await;
         }
+
+        Ok(elements)
     }
 
-    async fn download_single_list(
+    /// Merged and filtered contents of the set
+    pub(crate) async fn download_elements(
         &self,
-        cache: SourcesCache,
-        excluded: Arc<HashSet<IP>>,
-    ) -> Result<Vec<Expression>> {
-        // url will always exist at this moment
-        // so it's safe
-        let first_url = &self.urls[0];
+        context: &FetchContext,
+    ) -> Result<Vec<SetElement>> {
+        let elements = self.collect_elements(context, self.comments).await?;
+        Ok(elements.unwrap_or_default())
+    }
 
-        let mut entries = download_ips_list(first_url.clone(), cache, excluded).await?;
+    /// Contents of the set commented with their origins regardless of config
+    pub(crate) async fn download_origins(&self, context: &FetchContext) -> Result<Vec<SetElement>> {
+        let elements = self.collect_elements(context, true).await?;
+        Ok(elements.unwrap_or_default())
+    }
 
-        if self.entries_limit != 0 && entries.len() > self.entries_limit {
+    /// Nothing is returned when none of the lists changed since last update
+    async fn collect_elements(
+        &self,
+        context: &FetchContext,
+        comments: bool,
+    ) -> Result<Option<Vec<SetElement>>> {
+        let (mut elements, changed) = match self.format {
+            SourceFormat::Ips => self.collect_ips(context, comments).await?,
+            SourceFormat::Domains => (self.resolve_domains(context, comments).await?, true),
+        };
+
+        if !changed {
+            return Ok(None);
+        }
+
+        if let Some(timeout) = self.element_timeout {
+            let timeout = u32::try_from(timeout.as_secs()).unwrap_or(u32::MAX);
+            for element in &mut elements {
+                element.timeout.get_or_insert(timeout);
+            }
+        }
+
+        if self.entries_limit != 0 && elements.len() > self.entries_limit {
             log::warn!(
-                "Source {} exceeds maximum ({}) number of entries. Got {}. Truncating...",
-                first_url,
+                "Set {} exceeds maximum ({}) number of entries. Got {}. Truncating...",
+                self.set_name,
                 self.entries_limit,
-                entries.len()
+                elements.len()
             );
-            entries.truncate(self.entries_limit);
+            elements.truncate(self.entries_limit);
+        }
+
+        Ok(Some(elements))
+    }
+
+    /// All parts of the set and whether any of them changed
+    async fn collect_ips(
+        &self,
+        context: &FetchContext,
+        comments: bool,
+    ) -> Result<(Vec<SetElement>, bool)> {
+        // Inline entries are never changed, so they must not trigger reloading
+        // unless there is nothing else to compare with
+        let mut changed = self.urls.is_empty() && self.asn.is_none() && self.geoip.is_none();
+
+        let mut elements = match self.urls.len() {
+            0 => vec![],
+            1 => {
+                let (elements, modified) = self.download_single_list(context, comments).await?;
+                changed |= modified;
+                elements
+            }
+            _ => {
+                let (elements, modified) = self.download_multiple_lists(context, comments).await?;
+                changed |= modified;
+                elements
+            }
+        };
+
+        let inline = filter_excluded(self.entries.clone(), &context.excluded);
+        elements.extend(elements_from(inline, "inline", comments));
+
+        if let Some(asn) = &self.asn {
+            let (prefixes, modified) = asn.download(context).await?;
+            changed |= modified;
+
+            let prefixes = filter_excluded(prefixes.into_iter().collect(), &context.excluded);
+            elements.extend(elements_from(prefixes, "asn", comments));
         }
 
-        Ok(entries)
+        if let Some(geoip) = &self.geoip {
+            let (networks, modified) = geoip.download(context).await?;
+            changed |= modified;
+
+            let networks = filter_excluded(networks.into_iter().collect(), &context.excluded);
+            elements.extend(elements_from(networks, "geoip", comments));
+        }
+
+        Ok((elements, changed))
+    }
+
+    /// Elements with timeouts expire unless every update loads them again
+    fn refresh_always(&self) -> bool {
+        self.element_timeout.is_some()
+    }
+
+    /// Unchanged lists are reused when other parts of the set change
+    fn keeps_lists(&self) -> bool {
+        self.urls.len() > 1 || self.asn.is_some() || self.geoip.is_some()
+    }
+
+    async fn download_single_list(
+        &self,
+        context: &FetchContext,
+        comments: bool,
+    ) -> Result<(Vec<SetElement>, bool)> {
+        // url will always exist at this moment
+        // so it's safe
+        let first_url = &self.urls[0];
+
+        let (entries, changed) = download_ips_list(
+            first_url.clone(),
+            context.clone(),
+            self.refresh_always(),
+            self.keeps_lists(),
+        )
+        .await?;
+        let elements = elements_from(entries, &first_url.redacted(), comments).collect();
+
+        Ok((elements, changed))
     }
 
     async fn download_multiple_lists(
         &self,
-        cache: SourcesCache,
-        excluded: Arc<HashSet<IP>>,
-    ) -> Result<Vec<Expression>> {
+        context: &FetchContext,
+        comments: bool,
+    ) -> Result<(Vec<SetElement>, bool)> {
         let mut active_downloads = JoinSet::new();
         for url in &self.urls {
-            active_downloads.spawn(download_ips_list(
+            let download = download_ips_list(
                 url.clone(),
-                cache.clone(),
-                excluded.clone(),
-            ));
+                context.clone(),
+                self.refresh_always(),
+                self.keeps_lists(),
+            );
+            let origin = url.redacted();
+            active_downloads.spawn(async move { download.await.map(|entries| (entries, origin)) });
         }
 
         let mut entries = LinkedList::new();
+        let mut changed = false;
 
         while let Some(download) = active_downloads.join_next().await {
             let download = download?;
@@ -116,72 +330,222 @@ impl Source {
                 break;
             }
 
-            entries.extend(download?.into_iter());
+            let ((download, modified), origin) = download?;
+            changed |= modified;
+            entries.extend(elements_from(download, &origin, comments));
         }
 
-        Ok(entries.into_iter().collect())
+        Ok((entries.into_iter().collect(), changed))
     }
-}
 
-async fn download_ips_list(
-    url: Url,
-    sources_cache: SourcesCache,
-    excluded: Arc<HashSet<IP>>,
-) -> Result<Vec<Expression>> {
-    fn perform_filtering(mut original: HashSet<IP>, to_exclude: &HashSet<IP>) -> HashSet<IP> {
-        // HashSet has O(1)~ complexity for remove operation
-        // So we can remove items without any extra allocations
-
-        // Firstly, remove raw entries presented in list
-        //
-        // For 1.1.1.1 this will remove corresponding 1.1.1.1 entry.
-        // For 192.168.0.0/24 - corresponding 192.168.0.0/24 entry.
-        for excluded_ip in to_exclude {
-            original.remove(excluded_ip);
-        }
+    /// Runs list read elsewhere through the same exclusions and limits
+    pub(crate) fn parse_list(&self, raw_list: &str, context: &FetchContext) -> Vec<SetElement> {
+        let entries = list_entries(raw_list)
+            .filter_map(|entry| entry.parse::<IP>().ok())
+            .collect();
+
+        let mut entries: Vec<IP> = filter_excluded(entries, &context.excluded)
+            .into_iter()
+            .collect();
 
-        // Shrink original as retain method below has O(capacity) complexity
-        original.shrink_to_fit();
-
-        // Then remove *any* address or subnet that is contained in to_exclude list
-        // For 1.1.1.1/24 this will remove all 1.1.1.1, 1.1.1.2 ... 1.1.1.255 entries
-        //
-        // This algo has O(original * subnets) complexity.
-        // I don't know how to optimize this further yet.
-        let subnets: Vec<_> = to_exclude.iter().filter_map(IP::as_network).collect();
-        if !subnets.is_empty() {
-            original.retain(|ip| {
-                !subnets.iter().any(|subnet| match ip {
-                    IP::Single(ip) => subnet.contains(ip),
-                    IP::Network(ip) => subnet.contains(ip),
-                })
-            });
+        if self.entries_limit != 0 && entries.len() > self.entries_limit {
+            log::warn!(
+                "List exceeds maximum ({}) number of entries for {} set. Truncating...",
+                self.entries_limit,
+                self.set_name
+            );
+            entries.truncate(self.entries_limit);
         }
 
-        original
+        entries.into_iter().map(Into::into).collect()
+    }
+
+    pub(crate) async fn download_domains(&self, context: &FetchContext) -> Result<HashSet<String>> {
+        download_domains(&self.urls, context).await
+    }
+
+    async fn resolve_domains(
+        &self,
+        context: &FetchContext,
+        comments: bool,
+    ) -> Result<Vec<SetElement>> {
+        let domains = self.download_domains(context).await?;
+        log::info!(
+            "Resolving {} domains for {} set...",
+            domains.len(),
+            self.set_name
+        );
+
+        let mut resolved = self.resolve.resolve(domains, &context.dns_resolver).await;
+
+        let addresses = resolved.keys().cloned().collect();
+        let addresses = filter_excluded(addresses, &context.excluded);
+        resolved.retain(|ip, _| addresses.contains(ip));
+
+        let elements = resolved
+            .into_iter()
+            .map(|(ip, timeout)| SetElement {
+                ip,
+                timeout,
+                comment: comments.then(|| truncate_comment(&self.set_name)),
+            })
+            .collect();
+
+        Ok(elements)
     }
+}
 
-    let not_older_than = sources_cache.get(&url).await;
-    let provider = SourceProvider::new(url.clone())?;
+/// Wraps entries into elements commented with their origin if enabled
+fn elements_from(
+    entries: impl IntoIterator<Item = IP>,
+    origin: &str,
+    comments: bool,
+) -> impl Iterator<Item = SetElement> {
+    let comment = comments.then(|| truncate_comment(origin));
+
+    entries.into_iter().map(move |ip| SetElement {
+        ip,
+        timeout: None,
+        comment: comment.clone(),
+    })
+}
+
+/// Comments longer than 127 bytes are rejected by nftables
+fn truncate_comment(comment: &str) -> String {
+    let mut end = comment.len().min(127);
+    while !comment.is_char_boundary(end) {
+        end -= 1;
+    }
 
-    let FetchStatus::Success(info) = provider.fetch(not_older_than).await? else {
-        return Ok(vec![]);
+    comment[..end].to_string()
+}
+
+fn filter_excluded(mut original: HashSet<IP>, to_exclude: &HashSet<IP>) -> HashSet<IP> {
+    // HashSet has O(1)~ complexity for remove operation
+    // So we can remove items without any extra allocations
+
+    // Firstly, remove raw entries presented in list
+    //
+    // For 1.1.1.1 this will remove corresponding 1.1.1.1 entry.
+    // For 192.168.0.0/24 - corresponding 192.168.0.0/24 entry.
+    for excluded_ip in to_exclude {
+        original.remove(excluded_ip);
+    }
+
+    // Shrink original as retain method below has O(capacity) complexity
+    original.shrink_to_fit();
+
+    // Then remove *any* address or subnet that is contained in to_exclude list
+    // For 1.1.1.1/24 this will remove all 1.1.1.1, 1.1.1.2 ... 1.1.1.255 entries
+    //
+    // This algo has O(original * subnets) complexity.
+    // I don't know how to optimize this further yet.
+    let subnets: Vec<_> = to_exclude.iter().filter_map(IP::as_network).collect();
+    if !subnets.is_empty() {
+        original.retain(|ip| {
+            !subnets.iter().any(|subnet| match ip {
+                IP::Single(ip) => subnet.contains(ip),
+                IP::Network(ip) => subnet.contains(ip),
+            })
+        });
+    }
+
+    original
+}
+
+/// Entries of the list and whether it changed. Unchanged list is empty
+/// unless it is kept, so it can be reused when other lists change
+async fn download_ips_list(
+    url: SourceUrl,
+    context: FetchContext,
+    refresh: bool,
+    keep: bool,
+) -> Result<(Vec<IP>, bool)> {
+    let sources_cache = &context.cache;
+    let not_older_than = if refresh {
+        None
+    } else {
+        sources_cache.get(&url.url).await
     };
+    let provider = SourceProvider::new(url.clone(), &context.http_client)?;
 
-    sources_cache.set(&url, info.modified).await;
+    let status = match provider.fetch(not_older_than).await {
+        Ok(status) => status,
+        Err(error) if error.is::<VerificationError>() => {
+            log::error!("Rejecting update of {url}: {error}. Using last good list...");
 
-    let filtered_ips = perform_filtering(info.addresses, &excluded);
+            let last_good = sources_cache.get_last_good(&url.url).await;
+            let changed = last_good.is_some();
+            return Ok((last_good.unwrap_or_default(), changed));
+        }
+        Err(error) => return Err(error),
+    };
 
-    Ok(filtered_ips.into_iter().map(Into::into).collect())
+    let FetchStatus::Success(info) = status else {
+        let last = sources_cache.get_last_good(&url.url).await;
+        return Ok((last.unwrap_or_default(), false));
+    };
+
+    sources_cache.set(&url.url, info.modified).await;
+
+    let filtered_ips = filter_excluded(info.addresses, &context.excluded);
+    let entries: Vec<IP> = filtered_ips.into_iter().collect();
+
+    // Keep list only when it can be rejected or reused later
+    if keep || url.verify.is_some() {
+        sources_cache.set_last_good(&url.url, entries.clone()).await;
+    }
+
+    Ok((entries, true))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{SetTemplate, Source, SourcesCache};
+    use super::{
+        truncate_comment, DnsOptions, DnsResolver, FetchContext, Guards, HttpClient, HttpOptions,
+        ResolveOptions, SetTemplate, Source, SourceFormat, SourcesCache, IP,
+    };
     use std::collections::HashSet;
+    use std::path::Path;
     use std::sync::Arc;
+    use std::time::Duration;
     use url::Url;
 
+    fn local_source(paths: &[&Path]) -> Source {
+        Source {
+            set_name: "test_set".to_string(),
+            set_template: SetTemplate::default(),
+            urls: paths
+                .iter()
+                .map(|path| Url::from_file_path(path).unwrap().into())
+                .collect(),
+            entries: HashSet::new(),
+            entries_limit: 0,
+            guards: Guards::default(),
+            format: SourceFormat::default(),
+            resolve: ResolveOptions::default(),
+            dnsmasq: false,
+            asn: None,
+            geoip: None,
+            netns: None,
+            routing: None,
+            map: None,
+            element_timeout: None,
+            comments: false,
+        }
+    }
+
+    fn fetch_context(excluded: HashSet<IP>) -> FetchContext {
+        let dns_resolver = DnsResolver::new(DnsOptions::default());
+
+        FetchContext {
+            cache: SourcesCache::default(),
+            excluded: Arc::new(excluded),
+            http_client: HttpClient::new(HttpOptions::default(), dns_resolver.clone()).unwrap(),
+            dns_resolver,
+        }
+    }
+
     #[tokio::test]
     async fn download_local_and_filter() {
         let source_path = "/tmp/hirkn_download_local_and_filter.txt";
@@ -211,14 +575,9 @@ mod tests {
 
         std::fs::write(source_path, ips).unwrap();
 
-        let set = Source {
-            set_name: "test_set".to_string(),
-            set_template: SetTemplate::default(),
-            urls: vec![Url::from_file_path(source_path).unwrap()],
-            entries_limit: 0,
-        };
+        let set = local_source(&[Path::new(source_path)]);
 
-        let excluded = Arc::new(HashSet::from([
+        let context = fetch_context(HashSet::from([
             // IPv4 single address and subnet
             "192.168.1.1".parse().unwrap(),
             "192.168.0.10/28".parse().unwrap(),
@@ -229,8 +588,7 @@ mod tests {
             "10.0.0.0/8".parse().unwrap(),
         ]));
 
-        let cache = SourcesCache::default();
-        let downloaded = set.download_list(cache, excluded).await.unwrap();
+        let downloaded = set.download_list(&context).await.unwrap();
 
         std::fs::remove_file(source_path).unwrap();
 
@@ -239,4 +597,73 @@ mod tests {
         // 11.10.0.0/16
         assert_eq!(downloaded.len(), 3);
     }
+
+    #[tokio::test]
+    async fn download_inline_entries() {
+        let source_path = std::env::temp_dir().join(format!(
+            "hirkn_download_inline_entries_{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&source_path, "192.168.1.2\n").unwrap();
+
+        let mut set = local_source(&[&source_path]);
+        set.entries = HashSet::from(["1.0.0.1".parse().unwrap(), "10.1.1.1".parse().unwrap()]);
+
+        let context = fetch_context(HashSet::from(["10.0.0.0/8".parse().unwrap()]));
+
+        let downloaded = set.download_list(&context).await.unwrap();
+
+        // Inline entries alone must not replace the set when list is unchanged
+        let unchanged = set.download_list(&context).await.unwrap();
+
+        std::fs::remove_file(&source_path).unwrap();
+
+        // 192.168.1.2
+        // 1.0.0.1
+        assert_eq!(downloaded.len(), 2);
+        assert!(unchanged.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keep_unchanged_lists() {
+        let directory =
+            std::env::temp_dir().join(format!("hirkn_keep_unchanged_lists_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let first = directory.join("first.txt");
+        let second = directory.join("second.txt");
+        std::fs::write(&first, "1.1.1.1\n8.8.8.8\n").unwrap();
+        std::fs::write(&second, "9.9.9.9\n").unwrap();
+
+        let set = local_source(&[&first, &second]);
+        let context = fetch_context(HashSet::new());
+
+        let downloaded = set.download_list(&context).await.unwrap();
+
+        // Only second list looks changed, but the set must be refilled with both
+        let second_url = Url::from_file_path(&second).unwrap();
+        context.cache.set(&second_url, Duration::ZERO).await;
+        let refreshed = set.download_list(&context).await.unwrap();
+
+        let unchanged = set.download_list(&context).await.unwrap();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(downloaded.len(), 3);
+        assert_eq!(refreshed.len(), 3);
+        assert!(unchanged.is_empty());
+    }
+
+    #[test]
+    fn truncate_comment_at_char_boundary() {
+        assert_eq!(
+            truncate_comment("https://example.com"),
+            "https://example.com"
+        );
+
+        // Two bytes each, so 127th byte is in the middle of a character
+        let truncated = truncate_comment(&"é".repeat(100));
+        assert_eq!(truncated.len(), 126);
+        assert_eq!(truncated, "é".repeat(63));
+    }
 }
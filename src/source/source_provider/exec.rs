@@ -0,0 +1,100 @@
+use super::IPParsable;
+use anyhow::anyhow;
+use std::{path::PathBuf, process::Stdio, time::Duration};
+use tokio::process::Command;
+use url::Url;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Program which prints list to stdout.
+/// Configured as `exec:/path/to/program?arg=first&arg=second&timeout=30s`
+pub(crate) struct IPExecSource {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl IPExecSource {
+    pub(crate) fn new(url: &Url) -> anyhow::Result<Self> {
+        let mut args = vec![];
+        let mut timeout = DEFAULT_TIMEOUT;
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "arg" => args.push(value.into_owned()),
+                "timeout" => timeout = humantime::parse_duration(&value)?,
+                _ => return Err(anyhow!("Unknown exec source parameter {key}")),
+            }
+        }
+
+        Ok(Self {
+            program: url.path().into(),
+            args,
+            timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl IPParsable for IPExecSource {
+    type Error = anyhow::Error;
+
+    /// Output may change at any moment
+    async fn modified(&self) -> Result<Option<Duration>, Self::Error> {
+        Ok(None)
+    }
+
+    async fn fetch_raw(&self) -> Result<String, Self::Error> {
+        Ok(String::from_utf8(self.fetch_bytes().await?)?)
+    }
+
+    async fn fetch_bytes(&self) -> Result<Vec<u8>, Self::Error> {
+        let child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "{} did not finish in {}",
+                    self.program.display(),
+                    humantime::format_duration(self.timeout)
+                )
+            })??;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!(
+                "{} failed with {}: {}",
+                self.program.display(),
+                output.status,
+                stderr.trim()
+            ));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IPExecSource;
+    use crate::source::IPParsable;
+    use url::Url;
+
+    #[tokio::test]
+    async fn run_program() {
+        let url = Url::parse("exec:/bin/sh?arg=-c&arg=echo+1.1.1.1").unwrap();
+        let source = IPExecSource::new(&url).unwrap();
+        assert_eq!(source.fetch_raw().await.unwrap(), "1.1.1.1\n");
+
+        let url = Url::parse("exec:/bin/sh?arg=-c&arg=exit+1").unwrap();
+        let source = IPExecSource::new(&url).unwrap();
+        assert!(source.fetch_raw().await.is_err());
+    }
+}
@@ -1,12 +1,12 @@
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use serde::{de, Deserialize, Deserializer};
 use std::{
-    fmt::Formatter,
+    fmt::{Display, Formatter},
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     str::FromStr,
 };
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub(crate) enum IP {
     Single(IpAddr),
     Network(IpNet),
@@ -19,6 +19,14 @@ impl IP {
             Self::Network(net) => Some(net),
         }
     }
+
+    /// Single address is represented as /32 or /128 network
+    pub(crate) fn to_network(&self) -> IpNet {
+        match self {
+            Self::Single(ip) => IpNet::from(*ip),
+            Self::Network(net) => *net,
+        }
+    }
 }
 
 impl FromStr for IP {
@@ -37,6 +45,15 @@ impl FromStr for IP {
     }
 }
 
+impl Display for IP {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Single(ip) => ip.fmt(formatter),
+            Self::Network(ip_net) => ip_net.fmt(formatter),
+        }
+    }
+}
+
 impl From<IP> for nftables::expr::Expression {
     fn from(ip: IP) -> Self {
         use nftables::expr::{Expression, NamedExpression, Prefix};
@@ -55,6 +72,46 @@ impl From<IP> for nftables::expr::Expression {
     }
 }
 
+/// Address with optional element properties
+#[derive(Debug, Clone)]
+pub(crate) struct SetElement {
+    pub(crate) ip: IP,
+    /// Element timeout in seconds
+    pub(crate) timeout: Option<u32>,
+    /// Where the element came from
+    pub(crate) comment: Option<String>,
+}
+
+impl From<IP> for SetElement {
+    fn from(ip: IP) -> Self {
+        Self {
+            ip,
+            timeout: None,
+            comment: None,
+        }
+    }
+}
+
+impl From<SetElement> for nftables::expr::Expression {
+    fn from(element: SetElement) -> Self {
+        use nftables::expr::{Elem, NamedExpression};
+
+        let value = element.ip.into();
+        if element.timeout.is_none() && element.comment.is_none() {
+            return value;
+        }
+
+        let elem = Elem {
+            val: Box::new(value),
+            timeout: element.timeout,
+            expires: None,
+            comment: element.comment,
+        };
+
+        Self::Named(NamedExpression::Elem(elem))
+    }
+}
+
 impl PartialEq<IpAddr> for IP {
     fn eq(&self, other: &IpAddr) -> bool {
         match self {
@@ -106,7 +163,7 @@ impl<'de> Deserialize<'de> for IP {
     {
         struct IPVisitor;
 
-        impl<'vde> de::Visitor<'vde> for IPVisitor {
+        impl de::Visitor<'_> for IPVisitor {
             type Value = IP;
             fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
                 formatter.write_str("valid IPv4/IPv6 or valid CIDR notation")
@@ -134,7 +191,7 @@ mod tests {
     #[test]
     fn parse_ipv4_single() {
         let parsed: IP = "127.0.0.1".parse().unwrap();
-        let expected = Ipv4Addr::new(127, 0, 0, 1);
+        let expected = Ipv4Addr::LOCALHOST;
 
         assert_eq!(parsed, expected);
     }
@@ -142,7 +199,7 @@ mod tests {
     #[test]
     fn parse_ipv6_single() {
         let parsed: IP = "::1".parse().unwrap();
-        let expected = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1);
+        let expected = Ipv6Addr::LOCALHOST;
 
         assert_eq!(parsed, expected);
     }
@@ -151,7 +208,7 @@ mod tests {
     fn parse_ipv4_network() {
         let parsed: IP = "127.0.0.1/32".parse().unwrap();
 
-        let expected_ip = Ipv4Addr::new(127, 0, 0, 1);
+        let expected_ip = Ipv4Addr::LOCALHOST;
         let expected_net = Ipv4Net::new(expected_ip, 32).unwrap();
 
         assert_eq!(parsed, expected_net);
@@ -164,7 +221,7 @@ mod tests {
     fn parse_ipv6_network() {
         let parsed: IP = "::1/128".parse().unwrap();
 
-        let expected_ip = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1);
+        let expected_ip = Ipv6Addr::LOCALHOST;
         let expected_net = Ipv6Net::new(expected_ip, 128).unwrap();
 
         assert_eq!(parsed, expected_net);
@@ -1,23 +1,62 @@
 use super::IPParsable;
 use std::time::Duration;
 use std::{
-    fs::{File, Metadata},
+    fs::{self, File, Metadata},
     io::Read,
     path::{Path, PathBuf},
     time::SystemTime,
 };
 
+/// Single file, every file in directory or files matching glob pattern
 pub(crate) struct IPLocalSource {
-    path: PathBuf,
-    metadata: Metadata,
+    files: Vec<(PathBuf, Metadata)>,
+    /// Directories whose mtime changes when some list is added or removed
+    directories: Vec<Metadata>,
 }
 
 impl IPLocalSource {
-    pub(crate) fn new(path: impl AsRef<Path>) -> std::io::Result<Self> {
-        let path = path.as_ref().to_path_buf();
-        let metadata = path.metadata()?;
+    pub(crate) fn new(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let pattern = path.to_string_lossy();
 
-        Ok(Self { path, metadata })
+        let is_glob = pattern.contains(['*', '?', '[']);
+
+        let mut paths = if is_glob {
+            glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?
+        } else if path.is_dir() {
+            fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        // Keep lists order stable between updates
+        paths.sort_unstable();
+
+        let mut files = vec![];
+        for path in paths {
+            let metadata = path.metadata()?;
+            if metadata.is_file() {
+                files.push((path, metadata));
+            }
+        }
+
+        let mut directories: Vec<&Path> = if is_glob {
+            files.iter().filter_map(|(path, _)| path.parent()).collect()
+        } else if path.is_dir() {
+            vec![path]
+        } else {
+            vec![]
+        };
+        directories.dedup();
+
+        let directories = directories
+            .into_iter()
+            .map(Path::metadata)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { files, directories })
     }
 }
 
@@ -25,19 +64,91 @@ impl IPLocalSource {
 impl IPParsable for IPLocalSource {
     type Error = anyhow::Error;
 
+    /// Newest modification time among all lists
     async fn modified(&self) -> Result<Option<Duration>, Self::Error> {
-        let modified = self.metadata.modified()?;
-        let modified = modified.duration_since(SystemTime::UNIX_EPOCH)?;
-        Ok(Some(modified))
+        let mut newest = None;
+
+        for metadata in self.files.iter().map(|(_, metadata)| metadata) {
+            let modified = metadata.modified()?;
+            newest = newest.max(Some(modified));
+        }
+
+        // Directory is also modified when list is removed
+        for metadata in &self.directories {
+            newest = newest.max(Some(metadata.modified()?));
+        }
+
+        let Some(newest) = newest else {
+            return Ok(None);
+        };
+
+        Ok(Some(newest.duration_since(SystemTime::UNIX_EPOCH)?))
     }
 
     async fn fetch_raw(&self) -> Result<String, Self::Error> {
-        let buffer_size = self.metadata.len();
+        let buffer_size = self
+            .files
+            .iter()
+            .map(|(_, metadata)| metadata.len())
+            .sum::<u64>();
         let mut buffer = String::with_capacity(buffer_size.try_into()?);
 
-        let mut file = File::open(&self.path)?;
-        file.read_to_string(&mut buffer)?;
+        for (path, _) in &self.files {
+            let mut file = File::open(path)?;
+            file.read_to_string(&mut buffer)?;
+
+            // File may lack trailing newline, so don't glue its last line with the next one
+            buffer.push('\n');
+        }
+
+        Ok(buffer)
+    }
+
+    async fn fetch_bytes(&self) -> Result<Vec<u8>, Self::Error> {
+        let mut buffer = vec![];
+
+        // Lists are separated like in fetch_raw, but single file is kept
+        // intact, as databases and gzipped datasets are read this way too
+        for (index, (path, _)) in self.files.iter().enumerate() {
+            if index > 0 {
+                buffer.push(b'\n');
+            }
+            buffer.extend(fs::read(path)?);
+        }
 
         Ok(buffer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IPLocalSource;
+    use crate::source::IPParsable;
+
+    #[tokio::test]
+    async fn read_directory_and_glob() {
+        let directory = std::env::temp_dir().join(format!(
+            "hirkn_read_directory_and_glob_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("a.txt"), "1.1.1.1").unwrap();
+        std::fs::write(directory.join("b.txt"), "8.8.8.8\n").unwrap();
+        std::fs::write(directory.join("c.bak"), "9.9.9.9\n").unwrap();
+
+        let source = IPLocalSource::new(&directory).unwrap();
+        let listed = source.fetch_raw().await.unwrap();
+        let bytes = source.fetch_bytes().await.unwrap();
+
+        let source = IPLocalSource::new(directory.join("*.txt")).unwrap();
+        let matched = source.fetch_raw().await.unwrap();
+        let modified = source.modified().await.unwrap();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(listed, "1.1.1.1\n8.8.8.8\n\n9.9.9.9\n\n");
+        assert_eq!(bytes, b"1.1.1.1\n8.8.8.8\n\n9.9.9.9\n");
+        assert_eq!(matched, "1.1.1.1\n8.8.8.8\n\n");
+        assert!(modified.is_some());
+    }
+}
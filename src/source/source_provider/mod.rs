@@ -1,15 +1,16 @@
+mod exec;
 mod info;
 mod ip;
 mod local;
 mod remote;
 
+use self::{exec::IPExecSource, local::IPLocalSource, remote::IPRemoteSource};
 pub(crate) use self::{
     info::{FetchInfo, FetchStatus},
-    ip::IP,
+    ip::{SetElement, IP},
 };
-use self::{local::IPLocalSource, remote::IPRemoteSource};
+use super::{HttpClient, SourceUrl};
 use std::time::{Duration, SystemTime};
-use url::Url;
 
 #[async_trait]
 pub(crate) trait IPParsable {
@@ -19,7 +20,14 @@ pub(crate) trait IPParsable {
 
     async fn fetch_raw(&self) -> Result<String, Self::Error>;
 
-    async fn fetch(&self, not_older_than: Option<Duration>) -> Result<FetchStatus, Self::Error> {
+    /// Same as `fetch_raw` but for binary lists
+    async fn fetch_bytes(&self) -> Result<Vec<u8>, Self::Error>;
+
+    /// Returns modification time if list is newer than `not_older_than`
+    async fn check_modified(
+        &self,
+        not_older_than: Option<Duration>,
+    ) -> Result<Option<Duration>, Self::Error> {
         let modified = self.modified().await?.unwrap_or_else(|| {
             SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
@@ -28,16 +36,21 @@ pub(crate) trait IPParsable {
 
         if let Some(not_older_than) = not_older_than {
             if not_older_than >= modified {
-                return Ok(FetchStatus::NotModified);
+                return Ok(None);
             }
         }
 
+        Ok(Some(modified))
+    }
+
+    async fn fetch(&self, not_older_than: Option<Duration>) -> Result<FetchStatus, Self::Error> {
+        let Some(modified) = self.check_modified(not_older_than).await? else {
+            return Ok(FetchStatus::NotModified);
+        };
+
         let raw_list = self.fetch_raw().await?;
 
-        let addresses = raw_list
-            .lines()
-            .map(str::trim)
-            .filter(|entry| !(entry.is_empty() || entry.starts_with('#')))
+        let addresses = list_entries(&raw_list)
             .filter_map(|entry| entry.parse::<IP>().ok())
             .collect();
 
@@ -48,17 +61,29 @@ pub(crate) trait IPParsable {
     }
 }
 
+/// Skips empty lines and comments
+pub(crate) fn list_entries(raw_list: &str) -> impl Iterator<Item = &str> {
+    raw_list
+        .lines()
+        .map(str::trim)
+        .filter(|entry| !(entry.is_empty() || entry.starts_with('#')))
+}
+
 pub(crate) enum SourceProvider {
     Local(IPLocalSource),
-    Remote(IPRemoteSource),
+    Remote(Box<IPRemoteSource>),
+    Exec(IPExecSource),
 }
 
 impl SourceProvider {
-    pub(crate) fn new(url: Url) -> anyhow::Result<Self> {
-        if url.scheme() == "file" {
-            Ok(Self::Local(IPLocalSource::new(url.path())?))
-        } else {
-            Ok(Self::Remote(IPRemoteSource::new(url)))
+    pub(crate) fn new(url: SourceUrl, client: &HttpClient) -> anyhow::Result<Self> {
+        match url.url.scheme() {
+            "file" => Ok(Self::Local(IPLocalSource::new(url.url.path())?)),
+            "exec" => Ok(Self::Exec(IPExecSource::new(&url.url)?)),
+            _ => {
+                let client = client.client_for(url.bind.as_ref())?;
+                Ok(Self::Remote(Box::new(IPRemoteSource::new(url, client))))
+            }
         }
     }
 }
@@ -71,6 +96,7 @@ impl IPParsable for SourceProvider {
         match self {
             Self::Local(parser) => parser.modified().await,
             Self::Remote(parser) => parser.modified().await,
+            Self::Exec(parser) => parser.modified().await,
         }
     }
 
@@ -78,6 +104,15 @@ impl IPParsable for SourceProvider {
         match self {
             Self::Local(parser) => parser.fetch_raw().await,
             Self::Remote(parser) => parser.fetch_raw().await,
+            Self::Exec(parser) => parser.fetch_raw().await,
+        }
+    }
+
+    async fn fetch_bytes(&self) -> Result<Vec<u8>, Self::Error> {
+        match self {
+            Self::Local(parser) => parser.fetch_bytes().await,
+            Self::Remote(parser) => parser.fetch_bytes().await,
+            Self::Exec(parser) => parser.fetch_bytes().await,
         }
     }
 }
@@ -1,19 +1,24 @@
 use super::IPParsable;
+use crate::source::SourceUrl;
 use chrono::{DateTime, NaiveDateTime};
-use reqwest::Client;
+use reqwest::{Client, Method, RequestBuilder};
 use std::time::Duration;
 use url::Url;
 
 pub(crate) struct IPRemoteSource {
-    url: Url,
+    url: SourceUrl,
     client: Client,
 }
 
 impl IPRemoteSource {
-    pub(crate) fn new(url: Url) -> Self {
-        let client = Client::new();
+    pub(crate) fn new(url: SourceUrl, client: Client) -> Self {
         Self { url, client }
     }
+
+    fn request(&self, method: Method, url: &Url) -> RequestBuilder {
+        let request = self.client.request(method, url.clone());
+        self.url.request.apply(request)
+    }
 }
 
 #[async_trait]
@@ -21,7 +26,7 @@ impl IPParsable for IPRemoteSource {
     type Error = anyhow::Error;
 
     async fn modified(&self) -> Result<Option<Duration>, Self::Error> {
-        let response = self.client.head(self.url.clone()).send().await?;
+        let response = self.request(Method::HEAD, &self.url.url).send().await?;
         let response = response.error_for_status()?;
 
         let modified = response
@@ -37,9 +42,29 @@ impl IPParsable for IPRemoteSource {
     }
 
     async fn fetch_raw(&self) -> Result<String, Self::Error> {
-        let response = self.client.get(self.url.clone()).send().await?;
+        if self.url.verify.is_some() {
+            return Ok(String::from_utf8(self.fetch_bytes().await?)?);
+        }
+
+        let response = self.request(Method::GET, &self.url.url).send().await?;
         let response = response.error_for_status()?;
 
         Ok(response.text().await?)
     }
+
+    async fn fetch_bytes(&self) -> Result<Vec<u8>, Self::Error> {
+        let response = self.request(Method::GET, &self.url.url).send().await?;
+        let response = response.error_for_status()?;
+
+        let body = response.bytes().await?;
+
+        if let Some(verify) = &self.url.verify {
+            // Detached file may live on another host, so list headers and auth are not sent
+            let detached = self.client.get(verify.detached_url().clone());
+            let detached = detached.send().await?.error_for_status()?.text().await?;
+            verify.check(&body, &detached)?;
+        }
+
+        Ok(body.into())
+    }
 }
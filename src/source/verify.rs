@@ -0,0 +1,112 @@
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use url::Url;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum VerifyOptions {
+    /// Url of file with hex-encoded sha256 checksum in `sha256sum` format
+    Sha256(Url),
+    Minisign {
+        signature: Url,
+        public_key: String,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) struct VerificationError(String);
+
+impl Display for VerificationError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "List verification failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+impl VerifyOptions {
+    /// Url of checksum or signature to be downloaded alongside the list
+    pub(crate) fn detached_url(&self) -> &Url {
+        match self {
+            Self::Sha256(url) => url,
+            Self::Minisign { signature, .. } => signature,
+        }
+    }
+
+    pub(crate) fn check(&self, body: &[u8], detached: &str) -> Result<(), VerificationError> {
+        match self {
+            Self::Sha256(_) => {
+                let expected = detached
+                    .split_whitespace()
+                    .next()
+                    .ok_or_else(|| VerificationError("checksum file is empty".to_string()))?;
+
+                let actual = format!("{:x}", Sha256::digest(body));
+                if !actual.eq_ignore_ascii_case(expected) {
+                    return Err(VerificationError(format!(
+                        "checksum mismatch: expected {expected}, got {actual}"
+                    )));
+                }
+            }
+            Self::Minisign { public_key, .. } => {
+                let error = |error: minisign_verify::Error| VerificationError(error.to_string());
+
+                let public_key = PublicKey::from_base64(public_key).map_err(error)?;
+                let signature = Signature::decode(detached).map_err(error)?;
+                public_key.verify(body, &signature, false).map_err(error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VerifyOptions;
+
+    // sha256 of "1.1.1.1\n"
+    const CHECKSUM: &str = "454debca5afcd37addd0dcca05094625dcde6402510f8fc664cd567893ec7096";
+
+    const PUBLIC_KEY: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+
+    // Prehashed signature of "1.1.1.1\n" made by PUBLIC_KEY
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCI2n8RlSURsXw2WNAOqFYCp7z/Le9f0HB1MPkAb0CwmpfVNbkP/ZhKcqlblyuUw+tYvadf6gdtjMWqqQVK9B9wk=
+trusted comment: timestamp:0 file:list.txt
+HDI4ftrhmV9QRI353soaVVvnrFEIFiPgy1h+buT6+sl0EAkkI2GJaN1P2D1L8BAVgbsGu1RBG1ILnwbaJ3r+AA==
+";
+
+    fn options() -> VerifyOptions {
+        VerifyOptions::Sha256("https://example.com/list.txt.sha256".parse().unwrap())
+    }
+
+    #[test]
+    fn sha256_matches() {
+        let detached = format!("{CHECKSUM}  list.txt\n");
+        assert!(options().check(b"1.1.1.1\n", &detached).is_ok());
+    }
+
+    #[test]
+    fn sha256_mismatches() {
+        let detached = format!("{CHECKSUM}  list.txt\n");
+        assert!(options().check(b"0.0.0.0/0\n", &detached).is_err());
+        assert!(options().check(b"1.1.1.1\n", "").is_err());
+    }
+
+    #[test]
+    fn minisign_signature() {
+        let options = VerifyOptions::Minisign {
+            signature: "https://example.com/list.txt.minisig".parse().unwrap(),
+            public_key: PUBLIC_KEY.to_string(),
+        };
+
+        assert!(options.check(b"1.1.1.1\n", SIGNATURE).is_ok());
+        assert!(options.check(b"0.0.0.0/0\n", SIGNATURE).is_err());
+
+        let forged = SIGNATURE.replace("RUQBAgMEBQYHCI2n", "RUQBAgMEBQYHCI2o");
+        assert!(options.check(b"1.1.1.1\n", &forged).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+/// Writes temporary file next to target and renames it,
+/// so readers never see partially written file even after power loss
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&temporary, path)?;
+
+    Ok(())
+}